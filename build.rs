@@ -0,0 +1,7 @@
+fn main() {
+    // Without it, cargo reruns the script whenever any file of the crate changes
+    println!("cargo:rerun-if-changed=build.rs");
+    // Exposes the target triple, reported as a platform property
+    let target = std::env::var("TARGET").unwrap_or_default();
+    println!("cargo:rustc-env=ZKSYNC_TELEMETRY_TARGET={}", target);
+}
@@ -0,0 +1,271 @@
+//! Trail of recent activity attached to error reports.
+use crate::posthog::PostHogEvent;
+use crate::Severity;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Number of breadcrumbs kept by default
+pub(crate) const DEFAULT_CAPACITY: usize = 50;
+/// Longest message or string value kept, longer ones are truncated
+const MAX_VALUE_LENGTH: usize = 200;
+/// Values of data keys with any of these words, e.g. `private_key` or `apiToken`,
+/// are never sent
+const SENSITIVE_KEYS: &[&str] = &[
+    "apikey",
+    "auth",
+    "authorization",
+    "cookie",
+    "key",
+    "mnemonic",
+    "passphrase",
+    "passwd",
+    "password",
+    "secret",
+    "seed",
+    "token",
+];
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct Breadcrumb {
+    pub(crate) timestamp: DateTime<Utc>,
+    pub(crate) category: String,
+    pub(crate) message: String,
+    pub(crate) level: Severity,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub(crate) data: Map<String, Value>,
+}
+
+impl Breadcrumb {
+    pub(crate) fn new(category: &str, message: &str, level: Severity) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            category: category.to_string(),
+            message: truncate(message.to_string()),
+            level,
+            data: Map::new(),
+        }
+    }
+
+    /// Sets the data, scrubbing sensitive and oversized values
+    pub(crate) fn with_data(mut self, data: Map<String, Value>) -> Self {
+        self.data = scrub(data);
+        self
+    }
+
+    pub(crate) fn to_sentry(&self) -> sentry::Breadcrumb {
+        sentry::Breadcrumb {
+            timestamp: self.timestamp.into(),
+            category: Some(self.category.clone()),
+            message: Some(self.message.clone()),
+            level: self.level.to_sentry(),
+            data: self
+                .data
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Bounded buffer keeping the most recent breadcrumbs
+#[derive(Debug)]
+pub(crate) struct Breadcrumbs {
+    buffer: Mutex<VecDeque<Breadcrumb>>,
+    capacity: usize,
+}
+
+impl Breadcrumbs {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            buffer: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    pub(crate) fn add(&self, breadcrumb: Breadcrumb) {
+        if self.capacity == 0 {
+            return;
+        }
+        if let Ok(mut buffer) = self.buffer.lock() {
+            if buffer.len() == self.capacity {
+                buffer.pop_front();
+            }
+            buffer.push_back(breadcrumb);
+        }
+    }
+
+    /// Returns the breadcrumbs, oldest first
+    pub(crate) fn snapshot(&self) -> Vec<Breadcrumb> {
+        self.buffer
+            .lock()
+            .map(|buffer| buffer.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Attaches the breadcrumbs to events captured in `scope`, after any breadcrumbs
+/// recorded with the Sentry API
+pub(crate) fn apply_to_scope(breadcrumbs: &[Breadcrumb], scope: &mut sentry::Scope) {
+    if breadcrumbs.is_empty() {
+        return;
+    }
+    let breadcrumbs: Vec<_> = breadcrumbs.iter().map(Breadcrumb::to_sentry).collect();
+    scope.add_event_processor(move |mut event| {
+        event.breadcrumbs.values.extend(breadcrumbs.iter().cloned());
+        Some(event)
+    });
+}
+
+/// Attaches the breadcrumbs to a PostHog event as the `breadcrumbs` property
+pub(crate) fn apply_to_event(breadcrumbs: Vec<Breadcrumb>, event: &mut PostHogEvent) {
+    if !breadcrumbs.is_empty() {
+        event.insert_prop(
+            "breadcrumbs",
+            serde_json::to_value(breadcrumbs).unwrap_or_default(),
+        );
+    }
+}
+
+/// Redacts values of sensitive keys and truncates long strings, recursively
+fn scrub(data: Map<String, Value>) -> Map<String, Value> {
+    data.into_iter()
+        .map(|(key, value)| {
+            let value = if is_sensitive(&key) {
+                Value::String("[REDACTED]".to_string())
+            } else {
+                scrub_value(value)
+            };
+            (key, value)
+        })
+        .collect()
+}
+
+fn is_sensitive(key: &str) -> bool {
+    key_words(key)
+        .iter()
+        .any(|word| SENSITIVE_KEYS.contains(&word.as_str()))
+}
+
+/// Splits `snake_case`, `kebab-case` and `camelCase` keys into lowercase words
+fn key_words(key: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut previous_lowercase = false;
+    for c in key.chars() {
+        let boundary = !c.is_alphanumeric() || (c.is_uppercase() && previous_lowercase);
+        if boundary && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        if c.is_alphanumeric() {
+            word.extend(c.to_lowercase());
+        }
+        previous_lowercase = c.is_lowercase() || c.is_ascii_digit();
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn scrub_value(value: Value) -> Value {
+    match value {
+        Value::String(string) => Value::String(truncate(string)),
+        Value::Array(values) => Value::Array(values.into_iter().map(scrub_value).collect()),
+        Value::Object(map) => Value::Object(scrub(map)),
+        value => value,
+    }
+}
+
+fn truncate(mut string: String) -> String {
+    if let Some((index, _)) = string.char_indices().nth(MAX_VALUE_LENGTH) {
+        string.truncate(index);
+        string.push('…');
+    }
+    string
+}
+
+impl Default for Breadcrumbs {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_ring_buffer() {
+        let breadcrumbs = Breadcrumbs::new(2);
+        for message in ["first", "second", "third"] {
+            breadcrumbs.add(Breadcrumb::new("test", message, Severity::Info));
+        }
+        let messages: Vec<_> = breadcrumbs
+            .snapshot()
+            .into_iter()
+            .map(|breadcrumb| breadcrumb.message)
+            .collect();
+        assert_eq!(messages, ["second", "third"]);
+
+        let disabled = Breadcrumbs::new(0);
+        disabled.add(Breadcrumb::new("test", "ignored", Severity::Info));
+        assert!(disabled.snapshot().is_empty());
+    }
+
+    #[test]
+    fn test_scrubbing() {
+        let data = json!({
+            "network": "sepolia",
+            "private_key": "0x1234",
+            "nested": { "API_TOKEN": "abc", "count": 3 },
+            "apiKey": "abc",
+            "session-cookie": "abc",
+            "author": "alice",
+            "keyboard_layout": "dvorak",
+            "monkey_patch": true,
+            "long": "a".repeat(300),
+        });
+        let breadcrumb = Breadcrumb::new("test", &"b".repeat(300), Severity::Info)
+            .with_data(data.as_object().unwrap().clone());
+
+        assert_eq!(breadcrumb.message.chars().count(), MAX_VALUE_LENGTH + 1);
+        assert_eq!(breadcrumb.data["network"], "sepolia");
+        assert_eq!(breadcrumb.data["private_key"], "[REDACTED]");
+        assert_eq!(breadcrumb.data["nested"]["API_TOKEN"], "[REDACTED]");
+        assert_eq!(breadcrumb.data["nested"]["count"], 3);
+        assert_eq!(breadcrumb.data["apiKey"], "[REDACTED]");
+        assert_eq!(breadcrumb.data["session-cookie"], "[REDACTED]");
+        // Only whole words are sensitive
+        assert_eq!(breadcrumb.data["author"], "alice");
+        assert_eq!(breadcrumb.data["keyboard_layout"], "dvorak");
+        assert_eq!(breadcrumb.data["monkey_patch"], true);
+        assert!(breadcrumb.data["long"].as_str().unwrap().ends_with('…'));
+    }
+
+    #[test]
+    fn test_sentry_breadcrumbs() {
+        let breadcrumbs = [
+            Breadcrumb::new("event", "contract_deployed", Severity::Info),
+            Breadcrumb::new("app", "retrying", Severity::Warning),
+        ];
+        let events = sentry::test::with_captured_events(|| {
+            sentry::with_scope(
+                |scope| apply_to_scope(&breadcrumbs, scope),
+                || sentry::capture_message("test", sentry::Level::Error),
+            );
+        });
+
+        let sentry_breadcrumbs = &events[0].breadcrumbs.values;
+        assert_eq!(sentry_breadcrumbs.len(), 2);
+        assert_eq!(
+            sentry_breadcrumbs[0].message.as_deref(),
+            Some("contract_deployed")
+        );
+        assert_eq!(sentry_breadcrumbs[1].level, sentry::Level::Warning);
+    }
+}
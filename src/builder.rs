@@ -0,0 +1,300 @@
+//! Builder for [`Telemetry`] instances.
+use crate::breadcrumbs::{self, Breadcrumbs};
+use crate::context::TelemetryContext;
+use crate::panic::{self, PanicReporter, PanicReporting};
+use crate::platform::PlatformProperties;
+use crate::posthog::DEFAULT_HOST;
+use crate::rate_limit::{RateLimitConfig, RateLimiter};
+use crate::retry::RetryPolicy;
+use crate::sampling::SamplingConfig;
+use crate::scrub::ScrubConfig;
+use crate::transport::{HttpTransport, Transport};
+use crate::utils::debug_log;
+use crate::worker::PostHogWorker;
+use crate::{Telemetry, TelemetryConfig, TelemetryError, TelemetryKeys, TelemetryResult};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Configures and creates a [`Telemetry`] instance.
+///
+/// ```no_run
+/// # fn main() -> zksync_telemetry::TelemetryResult<()> {
+/// use zksync_telemetry::{Telemetry, TelemetryKeys};
+///
+/// let telemetry = Telemetry::builder("my-cli", "1.0.0", "my-cli")
+///     .keys(TelemetryKeys::new()?)
+///     .posthog_host("https://eu.i.posthog.com")
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct TelemetryBuilder {
+    app_name: String,
+    app_version: String,
+    environment: Option<String>,
+    platform_properties: PlatformProperties,
+    config_name: String,
+    keys: TelemetryKeys,
+    custom_config_path: Option<PathBuf>,
+    posthog_host: String,
+    transport: Option<Arc<dyn Transport>>,
+    connect_timeout: Duration,
+    request_timeout: Duration,
+    retry_policy: RetryPolicy,
+    sampling: SamplingConfig,
+    rate_limit: RateLimitConfig,
+    session_events: bool,
+    breadcrumb_capacity: usize,
+    panic_reporting: PanicReporting,
+    scrub: ScrubConfig,
+}
+
+impl TelemetryBuilder {
+    pub fn new(app_name: &str, app_version: &str, config_name: &str) -> Self {
+        Self {
+            app_name: app_name.to_string(),
+            app_version: app_version.to_string(),
+            environment: None,
+            platform_properties: PlatformProperties::none(),
+            config_name: config_name.to_string(),
+            keys: TelemetryKeys::default(),
+            custom_config_path: None,
+            posthog_host: DEFAULT_HOST.to_string(),
+            transport: None,
+            connect_timeout: HttpTransport::DEFAULT_CONNECT_TIMEOUT,
+            request_timeout: HttpTransport::DEFAULT_REQUEST_TIMEOUT,
+            retry_policy: RetryPolicy::default(),
+            sampling: SamplingConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            session_events: false,
+            breadcrumb_capacity: breadcrumbs::DEFAULT_CAPACITY,
+            panic_reporting: PanicReporting::default(),
+            scrub: ScrubConfig::default(),
+        }
+    }
+
+    /// Sets the environment reported to both backends,
+    /// `development` in debug builds and `production` otherwise by default
+    pub fn environment(mut self, environment: impl Into<String>) -> Self {
+        self.environment = Some(environment.into());
+        self
+    }
+
+    /// Selects the extra platform properties to report, none by default
+    pub fn platform_properties(mut self, platform_properties: PlatformProperties) -> Self {
+        self.platform_properties = platform_properties;
+        self
+    }
+
+    /// Sets the PostHog and Sentry keys. Without keys nothing is sent.
+    pub fn keys(mut self, keys: TelemetryKeys) -> Self {
+        self.keys = keys;
+        self
+    }
+
+    /// Overrides the default config file location
+    pub fn custom_config_path(mut self, path: Option<PathBuf>) -> Self {
+        self.custom_config_path = path;
+        self
+    }
+
+    /// Sets the PostHog instance to send events to, `https://us.i.posthog.com` by default
+    pub fn posthog_host(mut self, host: impl Into<String>) -> Self {
+        self.posthog_host = host.into();
+        self
+    }
+
+    /// Sets the HTTP transport used for PostHog, [`HttpTransport`] by default
+    pub fn transport(mut self, transport: impl Transport + 'static) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Sets the connect timeout of the default transport, 10 seconds by default
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Sets the timeout of a single request of the default transport, 30 seconds by default
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Sets how failed PostHog sends are retried, see [`RetryPolicy::default`]
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Sets the fraction of events sent per event name, all events are sent by default
+    pub fn sampling(mut self, sampling: SamplingConfig) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// Sets how many events and errors can be sent, see [`RateLimitConfig::default`]
+    pub fn rate_limit(mut self, rate_limit: RateLimitConfig) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+
+    /// Sends `session_started` when the instance is created and `session_ended` when
+    /// the session ends, e.g. when the instance is shut down or dropped.
+    /// Disabled by default.
+    pub fn session_events(mut self, enabled: bool) -> Self {
+        self.session_events = enabled;
+        self
+    }
+
+    /// Sets how many breadcrumbs are attached to error reports, 50 by default
+    pub fn breadcrumb_capacity(mut self, capacity: usize) -> Self {
+        self.breadcrumb_capacity = capacity;
+        self
+    }
+
+    /// Sets where panics are reported, see [`PanicReporting::default`].
+    ///
+    /// Panics are reported by a single process-wide hook, installed by the first
+    /// instance reporting them and running any previously installed hook afterwards.
+    /// It uses the context of the most recently built instance reporting panics,
+    /// and blocks the panicking thread until the report is sent, for up to 2 seconds
+    /// per backend. Dropping that instance stops reporting panics, while building
+    /// one with [`PanicReporting::Disabled`] leaves the current reporter alone.
+    pub fn panic_reporting(mut self, panic_reporting: PanicReporting) -> Self {
+        self.panic_reporting = panic_reporting;
+        self
+    }
+
+    /// Sets how personal information is scrubbed from events and error reports, see [`ScrubConfig`]
+    pub fn scrub(mut self, scrub: ScrubConfig) -> Self {
+        self.scrub = scrub;
+        self
+    }
+
+    /// Creates the telemetry instance.
+    /// Any failure is reported as [`TelemetryError::InitializationError`].
+    pub fn build(self) -> TelemetryResult<Telemetry> {
+        self.keys.validate().map_err(initialization_error)?;
+        let config = TelemetryConfig::new(&self.config_name, self.custom_config_path)
+            .map_err(initialization_error)?;
+        let context = Arc::new(TelemetryContext::new(
+            &self.app_name,
+            &self.app_version,
+            self.environment.as_deref(),
+            &self.platform_properties,
+        ));
+
+        let scrub = Arc::new(self.scrub);
+
+        let (posthog, sentry_guard) = if config.enabled {
+            let posthog = if let Some(key) = &self.keys.posthog_key {
+                let transport = self.transport.unwrap_or_else(|| {
+                    Arc::new(HttpTransport::with_timeouts(
+                        self.connect_timeout,
+                        self.request_timeout,
+                    ))
+                });
+                Some(PostHogWorker::spawn(
+                    key.clone(),
+                    &self.posthog_host,
+                    transport,
+                    self.retry_policy,
+                )?)
+            } else {
+                None
+            };
+
+            let sentry_guard = if let Some(dsn) = &self.keys.sentry_dsn {
+                // Parse the DSN up front, `sentry::init` panics on an invalid one
+                let dsn = dsn
+                    .expose_secret()
+                    .parse::<sentry::types::Dsn>()
+                    .map_err(|e| {
+                        TelemetryError::InitializationError(format!("Invalid Sentry DSN: {}", e))
+                    })?;
+                // The remaining context is attached to each report as tags.
+                // Default integrations are disabled so that panics are only reported
+                // by our own hook, even if another crate enables Sentry's panic feature.
+                let options = sentry::ClientOptions {
+                    dsn: Some(dsn),
+                    release: Some(context.release().to_string().into()),
+                    environment: Some(context.environment.clone().into()),
+                    default_integrations: false,
+                    before_send: Some({
+                        let scrub = scrub.clone();
+                        Arc::new(move |event| Some(scrub.scrub_sentry_event(event)))
+                    }),
+                    ..Default::default()
+                };
+
+                // Initialize Sentry and store the guard
+                Some(sentry::init(options))
+            } else {
+                None
+            };
+
+            if posthog.is_some() || sentry_guard.is_some() {
+                scrub.detect_identity();
+            }
+
+            (posthog, sentry_guard)
+        } else {
+            (None, None)
+        };
+        let breadcrumbs = Arc::new(Breadcrumbs::new(self.breadcrumb_capacity));
+
+        let sentry_client = sentry_guard
+            .as_ref()
+            .and_then(|_| sentry::Hub::current().client());
+        let reporter = PanicReporter::new(
+            self.panic_reporting,
+            posthog.clone(),
+            sentry_client,
+            config.instance_id.clone(),
+            context.clone(),
+            breadcrumbs.clone(),
+            scrub.clone(),
+        );
+        let panic_reporter = reporter.map(panic::set_reporter);
+
+        let telemetry = Telemetry {
+            context,
+            config,
+            posthog,
+            sentry_guard,
+            sampling: self.sampling,
+            rate_limiter: RateLimiter::new(self.rate_limit),
+            session_events: self.session_events,
+            breadcrumbs,
+            scrub,
+            panic_reporter,
+        };
+        telemetry.session_started();
+
+        Ok(telemetry)
+    }
+
+    /// Same as [`TelemetryBuilder::build`], but never fails. If initialization
+    /// fails, the error is logged and a disabled instance is returned instead.
+    pub fn build_best_effort(self) -> Telemetry {
+        let app_name = self.app_name.clone();
+        let app_version = self.app_version.clone();
+        match self.build() {
+            Ok(telemetry) => telemetry,
+            Err(e) => {
+                debug_log(&format!("{}, telemetry is disabled", e));
+                Telemetry::disabled(&app_name, &app_version)
+            }
+        }
+    }
+}
+
+fn initialization_error(e: TelemetryError) -> TelemetryError {
+    match e {
+        TelemetryError::InitializationError(_) => e,
+        e => TelemetryError::InitializationError(e.to_string()),
+    }
+}
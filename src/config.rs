@@ -19,7 +19,7 @@ pub struct TelemetryConfig {
 impl TelemetryConfig {
     /// Creates a new config instance
     pub fn new(config_name: &str, custom_path: Option<PathBuf>) -> TelemetryResult<Self> {
-        let config_path = Self::get_config_path(config_name, custom_path.clone());
+        let config_path = Self::get_config_path(config_name, custom_path.clone())?;
 
         // If config file exists, load it
         if config_path.exists() {
@@ -80,14 +80,31 @@ impl TelemetryConfig {
         Ok(config)
     }
 
+    /// Creates a disabled config that is never persisted
+    pub(crate) fn disabled() -> Self {
+        Self {
+            enabled: false,
+            instance_id: uuid::Uuid::new_v4().to_string(),
+            created_at: chrono::Utc::now(),
+            config_path: None,
+        }
+    }
+
     /// Gets the configuration file path
-    pub fn get_config_path(config_name: &str, custom_path: Option<PathBuf>) -> PathBuf {
+    pub fn get_config_path(
+        config_name: &str,
+        custom_path: Option<PathBuf>,
+    ) -> TelemetryResult<PathBuf> {
         if let Some(path) = custom_path {
-            path
+            Ok(path)
         } else {
             let proj_dirs = directories::ProjectDirs::from("com", "matter-labs", config_name)
-                .expect("Failed to get project directories");
-            proj_dirs.config_dir().join("telemetry.json")
+                .ok_or_else(|| {
+                    TelemetryError::InvalidPath(
+                        "Failed to determine the config directory".to_string(),
+                    )
+                })?;
+            Ok(proj_dirs.config_dir().join("telemetry.json"))
         }
     }
 
@@ -0,0 +1,237 @@
+//! Context attached to everything reported to PostHog and Sentry.
+use crate::platform::PlatformProperties;
+use crate::posthog::PostHogEvent;
+use crate::session::{Session, SessionStatus};
+use serde_json::{Map, Value};
+use std::sync::{Mutex, RwLock};
+
+/// Describes the app and the environment it runs in.
+///
+/// It's the single source of the properties attached to PostHog events and
+/// exceptions and of the tags attached to Sentry events, so both backends
+/// always receive the same context.
+#[derive(Debug)]
+pub(crate) struct TelemetryContext {
+    pub(crate) app_name: String,
+    pub(crate) app_version: String,
+    pub(crate) environment: String,
+    platform: Map<String, Value>,
+    super_properties: RwLock<Map<String, Value>>,
+    session: Mutex<Session>,
+}
+
+impl TelemetryContext {
+    pub(crate) fn new(
+        app_name: &str,
+        app_version: &str,
+        environment: Option<&str>,
+        platform: &PlatformProperties,
+    ) -> Self {
+        Self {
+            app_name: app_name.to_string(),
+            app_version: app_version.to_string(),
+            environment: environment
+                .map(str::to_string)
+                .unwrap_or_else(default_environment),
+            platform: platform.collect(),
+            super_properties: RwLock::default(),
+            session: Mutex::new(Session::process()),
+        }
+    }
+
+    /// The release reported to Sentry
+    pub(crate) fn release(&self) -> &str {
+        &self.app_version
+    }
+
+    pub(crate) fn register_property(&self, key: String, value: Value) {
+        if let Ok(mut super_properties) = self.super_properties.write() {
+            super_properties.insert(key, value);
+        }
+    }
+
+    pub(crate) fn register_properties(&self, properties: Map<String, Value>) {
+        if let Ok(mut super_properties) = self.super_properties.write() {
+            super_properties.extend(properties);
+        }
+    }
+
+    pub(crate) fn unregister_property(&self, key: &str) {
+        if let Ok(mut super_properties) = self.super_properties.write() {
+            super_properties.remove(key);
+        }
+    }
+
+    pub(crate) fn session_id(&self) -> String {
+        self.session
+            .lock()
+            .map(|session| session.id.clone())
+            .unwrap_or_default()
+    }
+
+    /// Replaces the current session with a new one
+    pub(crate) fn start_session(&self, session_id: String) {
+        if let Ok(mut session) = self.session.lock() {
+            *session = Session::new(session_id);
+        }
+    }
+
+    /// Ends the current session and returns the `session_ended` event reporting its
+    /// duration and `status`, or `None` if it has already ended
+    pub(crate) fn end_session(
+        &self,
+        status: SessionStatus,
+        distinct_id: &str,
+    ) -> Option<PostHogEvent> {
+        let duration = self.session.lock().ok()?.end()?;
+        let mut properties = Map::new();
+        properties.insert("duration_ms".into(), (duration.as_millis() as u64).into());
+        properties.insert("exit_status".into(), status.as_str().into());
+        let mut event = PostHogEvent::new("session_ended", distinct_id);
+        self.apply_to_event(&mut event, properties);
+        Some(event)
+    }
+
+    /// Adds the context to a PostHog event. Properties of the event itself take
+    /// precedence over super-properties, but not over the defaults.
+    pub(crate) fn apply_to_event(&self, event: &mut PostHogEvent, properties: Map<String, Value>) {
+        for (key, value) in self.merge(properties) {
+            event.insert_prop(key, value);
+        }
+    }
+
+    /// Adds the context and the properties of the error to a Sentry scope, with the
+    /// same precedence as [`TelemetryContext::apply_to_event`]. Arrays and objects
+    /// are set as extras, anything else as tags: strings as is, the rest as JSON.
+    pub(crate) fn apply_to_scope(&self, scope: &mut sentry::Scope, properties: Map<String, Value>) {
+        for (key, value) in self.merge(properties) {
+            match value {
+                Value::Array(_) | Value::Object(_) => scope.set_extra(&key, value),
+                Value::String(value) => scope.set_tag(&key, value),
+                value => scope.set_tag(&key, value.to_string()),
+            }
+        }
+    }
+
+    /// Properties of the event over super-properties, overridden by the defaults
+    fn merge(&self, properties: Map<String, Value>) -> Map<String, Value> {
+        let mut merged = self.super_properties();
+        merged.extend(properties);
+        merged.extend(self.default_properties());
+        merged
+    }
+
+    fn super_properties(&self) -> Map<String, Value> {
+        self.super_properties
+            .read()
+            .map(|super_properties| super_properties.clone())
+            .unwrap_or_default()
+    }
+
+    fn default_properties(&self) -> Map<String, Value> {
+        let mut properties = self.platform.clone();
+        properties.insert("session_id".into(), self.session_id().into());
+        properties.insert("app".into(), self.app_name.as_str().into());
+        properties.insert("app_version".into(), self.app_version.as_str().into());
+        properties.insert("environment".into(), self.environment.as_str().into());
+        properties.insert("platform".into(), std::env::consts::OS.into());
+        properties.insert("arch".into(), std::env::consts::ARCH.into());
+        properties.insert(
+            "zksync_telemetry_version".into(),
+            env!("CARGO_PKG_VERSION").into(),
+        );
+        properties
+    }
+}
+
+/// Same default as the Sentry client uses
+fn default_environment() -> String {
+    if cfg!(debug_assertions) {
+        "development".to_string()
+    } else {
+        "production".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backends_receive_same_context() {
+        let context = TelemetryContext::new(
+            "test-app",
+            "1.2.3",
+            Some("staging"),
+            &PlatformProperties::all(),
+        );
+        context.register_property("chain_id".into(), 270.into());
+        context.register_property("network".into(), "sepolia".into());
+
+        let mut event = PostHogEvent::new("test_event", "instance");
+        context.apply_to_event(&mut event, Map::new());
+        let payload: Value = serde_json::from_slice(&event.to_payload("phc_key").unwrap()).unwrap();
+        let posthog_properties = payload["properties"].as_object().unwrap();
+
+        let sentry_events = sentry::test::with_captured_events(|| {
+            sentry::with_scope(
+                |scope| context.apply_to_scope(scope, Map::new()),
+                || sentry::capture_message("test", sentry::Level::Error),
+            );
+        });
+        let sentry_tags = &sentry_events[0].tags;
+
+        assert_eq!(posthog_properties.len(), sentry_tags.len());
+        for (key, value) in posthog_properties {
+            let value = value
+                .as_str()
+                .map_or_else(|| value.to_string(), str::to_string);
+            assert_eq!(sentry_tags.get(key), Some(&value), "{}", key);
+        }
+        assert_eq!(sentry_tags["app_version"], context.release());
+        assert_eq!(sentry_tags["environment"], "staging");
+        assert_eq!(sentry_tags["chain_id"], "270");
+    }
+
+    #[test]
+    fn test_event_properties_precedence() {
+        let context = TelemetryContext::new("test-app", "1.2.3", None, &PlatformProperties::none());
+        context.register_property("network".into(), "sepolia".into());
+        context.register_property("chain_id".into(), 270.into());
+
+        let mut properties = Map::new();
+        properties.insert("network".into(), "mainnet".into());
+        properties.insert("app".into(), "other-app".into());
+        let mut event = PostHogEvent::new("test_event", "instance");
+        context.apply_to_event(&mut event, properties);
+        let payload: Value = serde_json::from_slice(&event.to_payload("phc_key").unwrap()).unwrap();
+
+        // Event properties override super-properties, but not the defaults
+        assert_eq!(payload["properties"]["network"], "mainnet");
+        assert_eq!(payload["properties"]["chain_id"], 270);
+        assert_eq!(payload["properties"]["app"], "test-app");
+    }
+
+    #[test]
+    fn test_error_properties_in_scope() {
+        let context = TelemetryContext::new("test-app", "1.2.3", None, &PlatformProperties::none());
+        context.register_property("network".into(), "sepolia".into());
+
+        let mut properties = Map::new();
+        properties.insert("network".into(), "mainnet".into());
+        properties.insert("command".into(), "deploy".into());
+        properties.insert("args".into(), serde_json::json!(["--verify"]));
+        let sentry_events = sentry::test::with_captured_events(|| {
+            sentry::with_scope(
+                |scope| context.apply_to_scope(scope, properties),
+                || sentry::capture_message("test", sentry::Level::Error),
+            );
+        });
+
+        let event = &sentry_events[0];
+        assert_eq!(event.tags["network"], "mainnet");
+        assert_eq!(event.tags["command"], "deploy");
+        assert!(!event.tags.contains_key("args"));
+        assert_eq!(event.extra["args"], serde_json::json!(["--verify"]));
+    }
+}
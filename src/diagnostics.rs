@@ -0,0 +1,65 @@
+//! Counters describing how telemetry delivery is going.
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Snapshot of the PostHog delivery counters of a [`Telemetry`](crate::Telemetry) instance.
+///
+/// Delivery failures never surface as errors from the detached tracking methods,
+/// this is the place to look for them instead.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TelemetryDiagnostics {
+    /// Events handed to the background thread
+    pub events_enqueued: u64,
+    /// Events accepted by PostHog
+    pub events_sent: u64,
+    /// Events that could not be delivered
+    pub send_failures: u64,
+    /// Events dropped without being sent because the queue was full
+    pub events_dropped: u64,
+    /// Description of the most recent delivery failure
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct DiagnosticsCounters {
+    events_enqueued: AtomicU64,
+    events_sent: AtomicU64,
+    send_failures: AtomicU64,
+    events_dropped: AtomicU64,
+    last_error: Mutex<Option<String>>,
+}
+
+impl DiagnosticsCounters {
+    pub(crate) fn record_enqueued(&self) {
+        self.events_enqueued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_sent(&self) {
+        self.events_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_failure(&self, error: &str) {
+        self.send_failures.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut last_error) = self.last_error.lock() {
+            *last_error = Some(error.to_string());
+        }
+    }
+
+    pub(crate) fn record_dropped(&self) {
+        self.events_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> TelemetryDiagnostics {
+        TelemetryDiagnostics {
+            events_enqueued: self.events_enqueued.load(Ordering::Relaxed),
+            events_sent: self.events_sent.load(Ordering::Relaxed),
+            send_failures: self.send_failures.load(Ordering::Relaxed),
+            events_dropped: self.events_dropped.load(Ordering::Relaxed),
+            last_error: self
+                .last_error
+                .lock()
+                .ok()
+                .and_then(|last_error| last_error.clone()),
+        }
+    }
+}
@@ -0,0 +1,364 @@
+//! Errors converted into reports with their cause chain and backtrace.
+use crate::rate_limit::error_fingerprint;
+use crate::{Severity, TelemetryProps};
+use sentry::protocol::{Event, Exception, Frame, Mechanism, Stacktrace};
+use serde_json::{Map, Value};
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::error::Error;
+
+/// Error to report, accepted by [`Telemetry::track_error`](crate::Telemetry::track_error).
+///
+/// It's created from any error reference such as `&(dyn Error + Send + Sync + 'static)`,
+/// `Box<dyn Error>` or [`anyhow::Error`], and keeps the whole `source()` chain.
+/// Backtraces captured by `anyhow` are included, other ones can be added with
+/// [`ErrorReport::with_backtrace`].
+///
+/// Errors are reported as handled with [`Severity::Error`] unless specified otherwise:
+///
+/// ```no_run
+/// # async fn run(telemetry: &zksync_telemetry::Telemetry) -> zksync_telemetry::TelemetryResult<()> {
+/// use std::backtrace::Backtrace;
+/// use zksync_telemetry::{ErrorReport, Severity};
+///
+/// let error = std::io::Error::other("disk full");
+/// telemetry.track_error(&error).await?;
+///
+/// let error = anyhow::anyhow!("deployment failed");
+/// telemetry.track_error(error).await?;
+///
+/// let backtrace = Backtrace::capture();
+/// let error = std::io::Error::other("disk full");
+/// telemetry
+///     .track_error(ErrorReport::new(&error).with_backtrace(&backtrace))
+///     .await?;
+///
+/// let error = std::io::Error::other("fallback RPC unreachable");
+/// telemetry
+///     .track_error(ErrorReport::new(&error).with_severity(Severity::Warning))
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ErrorReport {
+    /// Errors of the chain, root cause first as in Sentry
+    exceptions: Vec<Exception>,
+    /// Set instead of the exceptions for reports of messages
+    message: Option<String>,
+    fingerprint: String,
+    properties: Map<String, Value>,
+    severity: Severity,
+    handled: bool,
+    /// Type of the Sentry exception mechanism
+    mechanism: &'static str,
+}
+
+impl ErrorReport {
+    pub fn new<E: Error + ?Sized>(error: &E) -> Self {
+        let fingerprint = error_fingerprint(&error);
+        Self {
+            exceptions: sentry::event_from_error(error).exception.values,
+            message: None,
+            fingerprint,
+            properties: Map::new(),
+            severity: Severity::Error,
+            handled: true,
+            mechanism: "generic",
+        }
+    }
+
+    /// Report of a message rather than an error, see [`Telemetry::capture_message`]
+    ///
+    /// [`Telemetry::capture_message`]: crate::Telemetry::capture_message
+    pub(crate) fn from_message(message: &str) -> Self {
+        Self {
+            exceptions: Vec::new(),
+            message: Some(message.to_string()),
+            fingerprint: format!("message: {}", message),
+            properties: Map::new(),
+            severity: Severity::Error,
+            handled: true,
+            mechanism: "generic",
+        }
+    }
+
+    /// Report of a panic, unhandled and fatal
+    pub(crate) fn from_panic(message: &str, backtrace: &Backtrace) -> Self {
+        let exception = Exception {
+            ty: "panic".to_string(),
+            value: Some(message.to_string()),
+            ..Default::default()
+        };
+        Self {
+            exceptions: vec![exception],
+            message: None,
+            fingerprint: format!("panic: {}", message),
+            properties: Map::new(),
+            severity: Severity::Fatal,
+            handled: false,
+            mechanism: "panic",
+        }
+        .with_backtrace(backtrace)
+    }
+
+    pub fn with_severity(mut self, severity: Severity) -> Self {
+        self.severity = severity;
+        self
+    }
+
+    /// Whether the app handled the error, `true` by default.
+    /// Unhandled errors are the ones the app crashes or exits on.
+    pub fn with_handled(mut self, handled: bool) -> Self {
+        self.handled = handled;
+        self
+    }
+
+    /// Attaches the backtrace to the error, if it was captured
+    pub fn with_backtrace(mut self, backtrace: &Backtrace) -> Self {
+        if backtrace.status() == BacktraceStatus::Captured {
+            if let Some(exception) = self.exceptions.last_mut() {
+                exception.stacktrace = parse_backtrace(&backtrace.to_string());
+            }
+        }
+        self
+    }
+
+    /// Attaches properties describing the circumstances of the error, e.g. the command
+    /// being run. They're sent as tags and extras to Sentry, and as properties to PostHog.
+    pub fn with_properties(mut self, properties: TelemetryProps) -> Self {
+        self.properties
+            .extend(properties.to_map().unwrap_or_default());
+        self
+    }
+
+    /// Identifies repetitions of the error, see [`error_fingerprint`]
+    pub(crate) fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    pub(crate) fn properties(&self) -> &Map<String, Value> {
+        &self.properties
+    }
+
+    pub(crate) fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub(crate) fn handled(&self) -> bool {
+        self.handled
+    }
+
+    /// Type of the outermost error as reported to Sentry, `panic` or `message`
+    pub(crate) fn kind(&self) -> &str {
+        match self.exceptions.last() {
+            Some(exception) => &exception.ty,
+            None => "message",
+        }
+    }
+
+    /// Errors of the chain, root cause first
+    pub(crate) fn exceptions(&self) -> &[Exception] {
+        &self.exceptions
+    }
+
+    pub(crate) fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    pub(crate) fn to_sentry_event(&self) -> Event<'static> {
+        if let Some(message) = &self.message {
+            return Event {
+                message: Some(message.clone()),
+                level: self.severity.to_sentry(),
+                ..Default::default()
+            };
+        }
+
+        let mut exceptions = self.exceptions.clone();
+        if let Some(exception) = exceptions.last_mut() {
+            exception.mechanism = Some(Mechanism {
+                ty: self.mechanism.to_string(),
+                handled: Some(self.handled),
+                ..Default::default()
+            });
+        }
+        Event {
+            exception: exceptions.into(),
+            level: self.severity.to_sentry(),
+            ..Default::default()
+        }
+    }
+}
+
+impl<E: Error + ?Sized> From<&E> for ErrorReport {
+    fn from(error: &E) -> Self {
+        Self::new(error)
+    }
+}
+
+impl From<Box<dyn Error>> for ErrorReport {
+    fn from(error: Box<dyn Error>) -> Self {
+        Self::new(error.as_ref())
+    }
+}
+
+impl From<Box<dyn Error + Send + Sync>> for ErrorReport {
+    fn from(error: Box<dyn Error + Send + Sync>) -> Self {
+        Self::new(error.as_ref())
+    }
+}
+
+impl From<anyhow::Error> for ErrorReport {
+    fn from(error: anyhow::Error) -> Self {
+        let inner: &(dyn Error + Send + Sync) = error.as_ref();
+        Self::new(inner).with_backtrace(error.backtrace())
+    }
+}
+
+/// Parses the output of [`Backtrace`]'s `Display` implementation, which has
+/// a line with the function of each frame followed by its location, if known:
+///
+/// ```text
+///    0: app::deploy
+///              at ./src/main.rs:10:5
+/// ```
+fn parse_backtrace(backtrace: &str) -> Option<Stacktrace> {
+    let mut frames: Vec<Frame> = Vec::new();
+    for line in backtrace.lines().map(str::trim) {
+        if let Some(location) = line.strip_prefix("at ") {
+            if let Some(frame) = frames.last_mut() {
+                let mut parts = location.rsplitn(3, ':');
+                let colno = parts.next().and_then(|colno| colno.parse().ok());
+                let lineno = parts.next().and_then(|lineno| lineno.parse().ok());
+                match (parts.next(), lineno, colno) {
+                    (Some(filename), Some(lineno), Some(colno)) => {
+                        frame.filename = Some(filename.to_string());
+                        frame.lineno = Some(lineno);
+                        frame.colno = Some(colno);
+                    }
+                    _ => frame.filename = Some(location.to_string()),
+                }
+            }
+        } else if let Some((index, function)) = line.split_once(": ") {
+            if index.parse::<usize>().is_ok() {
+                frames.push(Frame {
+                    function: Some(function.to_string()),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+    // Backtraces start with the innermost frame, Sentry stacktraces with the outermost one
+    Stacktrace::from_frames_reversed(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt;
+
+    #[derive(Debug)]
+    struct DeploymentError(std::io::Error);
+
+    impl fmt::Display for DeploymentError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("deployment failed")
+        }
+    }
+
+    impl Error for DeploymentError {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    #[test]
+    fn test_error_chain() {
+        let error = DeploymentError(std::io::Error::other("disk full"));
+        let reports = [
+            ErrorReport::from(&error),
+            ErrorReport::from(
+                Box::new(DeploymentError(std::io::Error::other("disk full"))) as Box<dyn Error>,
+            ),
+            ErrorReport::from(anyhow::Error::new(DeploymentError(std::io::Error::other(
+                "disk full",
+            )))),
+        ];
+
+        for report in reports {
+            let exceptions = report.exceptions();
+            assert_eq!(exceptions.len(), 2);
+            assert_eq!(exceptions[0].value.as_deref(), Some("disk full"));
+            assert_eq!(exceptions[1].ty, "DeploymentError");
+            assert_eq!(exceptions[1].value.as_deref(), Some("deployment failed"));
+            assert_eq!(report.fingerprint(), error_fingerprint(&error));
+        }
+    }
+
+    #[test]
+    fn test_anyhow_context() {
+        let error = anyhow::Error::new(std::io::Error::other("disk full")).context("deploying");
+        let report = ErrorReport::from(error);
+
+        let values: Vec<_> = report
+            .exceptions()
+            .iter()
+            .map(|exception| exception.value.as_deref().unwrap())
+            .collect();
+        assert_eq!(values, ["disk full", "deploying"]);
+    }
+
+    #[test]
+    fn test_sentry_event() {
+        let error = DeploymentError(std::io::Error::other("disk full"));
+        let event = ErrorReport::new(&error)
+            .with_severity(Severity::Fatal)
+            .with_handled(false)
+            .to_sentry_event();
+
+        assert_eq!(event.level, sentry::Level::Fatal);
+        let mechanism = event.exception[1].mechanism.as_ref().unwrap();
+        assert_eq!(mechanism.handled, Some(false));
+        assert!(event.exception[0].mechanism.is_none());
+    }
+
+    #[test]
+    fn test_message_sentry_event() {
+        let event = ErrorReport::from_message("fallback RPC used")
+            .with_severity(Severity::Warning)
+            .to_sentry_event();
+
+        assert_eq!(event.message.as_deref(), Some("fallback RPC used"));
+        assert_eq!(event.level, sentry::Level::Warning);
+        assert!(event.exception.is_empty());
+    }
+
+    #[test]
+    fn test_parse_backtrace() {
+        let backtrace = "   0: app::deploy
+             at ./src/deploy.rs:10:5
+   1: app::main
+             at ./src/main.rs:3:5
+   2: __libc_start_main
+";
+        let frames = parse_backtrace(backtrace).unwrap().frames;
+
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].function.as_deref(), Some("__libc_start_main"));
+        assert_eq!(frames[0].filename, None);
+        assert_eq!(frames[2].function.as_deref(), Some("app::deploy"));
+        assert_eq!(frames[2].filename.as_deref(), Some("./src/deploy.rs"));
+        assert_eq!(frames[2].lineno, Some(10));
+        assert_eq!(frames[2].colno, Some(5));
+    }
+
+    #[test]
+    fn test_backtrace_is_attached_if_captured() {
+        let error = std::io::Error::other("disk full");
+        let report = ErrorReport::new(&error).with_backtrace(&Backtrace::force_capture());
+        assert!(report.exceptions()[0].stacktrace.is_some());
+
+        let report = ErrorReport::new(&error).with_backtrace(&Backtrace::disabled());
+        assert!(report.exceptions()[0].stacktrace.is_none());
+    }
+}
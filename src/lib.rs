@@ -1,13 +1,58 @@
 // lib.rs
+mod breadcrumbs;
+pub mod builder;
 pub mod config;
+mod context;
+pub mod diagnostics;
 pub mod error;
+pub mod error_report;
 pub mod keys;
+#[cfg(feature = "log")]
+pub mod logger;
+pub mod panic;
+pub mod platform;
+mod posthog;
 pub mod properties;
+pub mod rate_limit;
+pub mod retry;
+pub mod sampling;
+pub mod scrub;
+pub mod secret;
+pub mod session;
+pub mod severity;
+pub mod span;
 pub mod telemetry;
+#[cfg(test)]
+mod test_utils;
+#[cfg(feature = "tracing")]
+pub mod tracing_layer;
+pub mod transport;
 mod utils;
+mod worker;
 
+pub use builder::TelemetryBuilder;
 pub use config::TelemetryConfig;
+pub use diagnostics::TelemetryDiagnostics;
 pub use error::{TelemetryError, TelemetryResult};
-pub use keys::TelemetryKeys;
+pub use error_report::ErrorReport;
+pub use keys::{KeySource, TelemetryKeys, TelemetryKeysResolver};
+#[cfg(feature = "log")]
+pub use logger::TelemetryLogger;
+pub use panic::PanicReporting;
+pub use platform::PlatformProperties;
 pub use properties::TelemetryProps;
-pub use telemetry::{get_telemetry, init_telemetry, Telemetry};
+pub use rate_limit::{Quota, RateLimitConfig};
+pub use retry::RetryPolicy;
+pub use sampling::SamplingConfig;
+pub use scrub::ScrubConfig;
+pub use secret::SecretString;
+pub use session::SessionStatus;
+pub use severity::Severity;
+pub use span::{Span, SpanOutcome};
+pub use telemetry::{
+    get_telemetry, init_telemetry, init_telemetry_best_effort, init_telemetry_blocking,
+    shutdown_telemetry, Telemetry,
+};
+#[cfg(feature = "tracing")]
+pub use tracing_layer::TelemetryLayer;
+pub use transport::{HttpTransport, Transport, TransportRequest, TransportResponse};
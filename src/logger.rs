@@ -0,0 +1,197 @@
+//! [`log`] integration, enabled with the `log` feature.
+use crate::breadcrumbs::Breadcrumb;
+use crate::{get_telemetry, Severity, Telemetry};
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+use std::fmt;
+
+/// [`Log`] implementation passing records through to an inner logger while
+/// reporting them to [`Telemetry`]:
+/// - `ERROR` records are tracked as errors
+/// - records up to the breadcrumb level (`INFO` by default) are kept as breadcrumbs
+///   and attached to subsequent error reports
+///
+/// Records are reported even if the inner logger filters them out.
+///
+/// ```ignore
+/// use log::LevelFilter;
+/// use zksync_telemetry::TelemetryLogger;
+///
+/// let inner = env_logger::Builder::from_default_env().build();
+/// let max_level = inner.filter().max(LevelFilter::Info);
+/// TelemetryLogger::new(inner).install(max_level).unwrap();
+/// ```
+pub struct TelemetryLogger<L> {
+    inner: L,
+    telemetry: Option<&'static Telemetry>,
+    breadcrumb_level: LevelFilter,
+}
+
+impl<L: Log> TelemetryLogger<L> {
+    /// Wraps `inner`, reporting to the global instance set by
+    /// [`init_telemetry`](crate::init_telemetry). Until it's set, nothing is reported.
+    pub fn new(inner: L) -> Self {
+        Self {
+            inner,
+            telemetry: None,
+            breadcrumb_level: LevelFilter::Info,
+        }
+    }
+
+    /// Reports to the given instance instead of the global one
+    pub fn with_telemetry(mut self, telemetry: &'static Telemetry) -> Self {
+        self.telemetry = Some(telemetry);
+        self
+    }
+
+    /// Sets the most verbose level kept as breadcrumbs, `LevelFilter::Off` disables them
+    pub fn breadcrumb_level(mut self, level: LevelFilter) -> Self {
+        self.breadcrumb_level = level;
+        self
+    }
+
+    /// Installs the logger as the global logger with the given maximum level
+    pub fn install(self, max_level: LevelFilter) -> Result<(), SetLoggerError>
+    where
+        L: 'static,
+    {
+        log::set_boxed_logger(Box::new(self))?;
+        log::set_max_level(max_level);
+        Ok(())
+    }
+
+    fn telemetry(&self) -> Option<&'static Telemetry> {
+        self.telemetry.or_else(get_telemetry)
+    }
+}
+
+impl<L: Log> Log for TelemetryLogger<L> {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        self.inner.enabled(metadata)
+            || metadata.level() <= self.breadcrumb_level
+            || metadata.level() == Level::Error
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if self.inner.enabled(record.metadata()) {
+            self.inner.log(record);
+        }
+
+        let Some(telemetry) = self.telemetry() else {
+            return;
+        };
+        let message = record.args().to_string();
+        if record.level() == Level::Error {
+            telemetry.track_error_detached(&LogError {
+                message: message.clone(),
+            });
+        }
+        if record.level() <= self.breadcrumb_level {
+            let level = match record.level() {
+                Level::Error => Severity::Error,
+                Level::Warn => Severity::Warning,
+                Level::Info => Severity::Info,
+                Level::Debug | Level::Trace => Severity::Debug,
+            };
+            telemetry.record_breadcrumb(Breadcrumb::new(record.target(), &message, level));
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Error reported for `ERROR` records
+#[derive(Debug)]
+struct LogError {
+    message: String,
+}
+
+impl fmt::Display for LogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for LogError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{enabled_telemetry, sent_requests};
+    use std::sync::Mutex;
+
+    /// Records the messages it's given
+    #[derive(Default)]
+    struct RecordingLogger {
+        messages: Mutex<Vec<String>>,
+    }
+
+    impl Log for RecordingLogger {
+        fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+            metadata.level() <= Level::Warn
+        }
+
+        fn log(&self, record: &Record<'_>) {
+            self.messages
+                .lock()
+                .unwrap()
+                .push(record.args().to_string());
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn log(logger: &impl Log, level: Level, message: &str) {
+        logger.log(
+            &Record::builder()
+                .level(level)
+                .target("test")
+                .args(format_args!("{}", message))
+                .build(),
+        );
+    }
+
+    #[test]
+    fn test_logger() {
+        let (_temp_dir, telemetry, transport) = enabled_telemetry();
+        let telemetry: &'static Telemetry = Box::leak(Box::new(telemetry));
+        let logger = TelemetryLogger::new(RecordingLogger::default()).with_telemetry(telemetry);
+
+        log(&logger, Level::Debug, "debug message");
+        log(&logger, Level::Info, "connecting");
+        log(&logger, Level::Warn, "retrying");
+        log(&logger, Level::Error, "connection failed");
+
+        assert_eq!(
+            *logger.inner.messages.lock().unwrap(),
+            ["retrying", "connection failed"]
+        );
+        let requests = sent_requests(telemetry, &transport);
+        assert_eq!(requests.len(), 1);
+        let properties = &requests[0]["properties"];
+        assert_eq!(
+            properties["$exception_list"][0]["value"],
+            "connection failed"
+        );
+        let breadcrumbs: Vec<_> = properties["breadcrumbs"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|breadcrumb| {
+                (
+                    breadcrumb["message"].as_str().unwrap(),
+                    breadcrumb["level"].as_str().unwrap(),
+                    breadcrumb["category"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            breadcrumbs,
+            [
+                ("connecting", "info", "test"),
+                ("retrying", "warning", "test")
+            ]
+        );
+    }
+}
@@ -0,0 +1,256 @@
+//! Panic reporting through a single process-wide hook.
+use crate::breadcrumbs::{self, Breadcrumbs};
+use crate::context::TelemetryContext;
+use crate::error_report::ErrorReport;
+use crate::posthog::PostHogEvent;
+use crate::scrub::ScrubConfig;
+use crate::utils::debug_log;
+use crate::worker::PostHogWorker;
+use once_cell::sync::Lazy;
+use serde_json::Map;
+use std::backtrace::Backtrace;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Once, RwLock};
+use std::time::Duration;
+
+/// How long a panicking thread waits for the panic report to be sent to each backend
+const PANIC_REPORT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Panic reporter with the id returned by [`set_reporter`]
+type OwnedReporter = (u64, Arc<PanicReporter>);
+
+/// Reporter used by the hook, replaced by every instance built with panic reporting
+/// and cleared when that instance is dropped
+static REPORTER: Lazy<RwLock<Option<OwnedReporter>>> = Lazy::new(|| RwLock::new(None));
+static NEXT_REPORTER_ID: AtomicU64 = AtomicU64::new(0);
+static INSTALL_HOOK: Once = Once::new();
+
+/// Where panics are reported, see [`TelemetryBuilder::panic_reporting`]
+///
+/// [`TelemetryBuilder::panic_reporting`]: crate::TelemetryBuilder::panic_reporting
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PanicReporting {
+    /// Panics aren't reported by this instance
+    Disabled,
+    /// Like errors: to Sentry if it's configured, to PostHog otherwise
+    #[default]
+    PreferSentry,
+    /// To every configured backend
+    AllBackends,
+}
+
+/// Reports panics with the context of one telemetry instance
+pub(crate) struct PanicReporter {
+    posthog: Option<PostHogWorker>,
+    sentry: Option<Arc<sentry::Client>>,
+    distinct_id: String,
+    context: Arc<TelemetryContext>,
+    breadcrumbs: Arc<Breadcrumbs>,
+    scrub: Arc<ScrubConfig>,
+}
+
+impl PanicReporter {
+    /// Returns `None` if panics aren't reported to any backend
+    pub(crate) fn new(
+        reporting: PanicReporting,
+        posthog: Option<PostHogWorker>,
+        sentry: Option<Arc<sentry::Client>>,
+        distinct_id: String,
+        context: Arc<TelemetryContext>,
+        breadcrumbs: Arc<Breadcrumbs>,
+        scrub: Arc<ScrubConfig>,
+    ) -> Option<Self> {
+        let posthog = match reporting {
+            PanicReporting::Disabled => return None,
+            PanicReporting::PreferSentry if sentry.is_some() => None,
+            _ => posthog,
+        };
+        if posthog.is_none() && sentry.is_none() {
+            return None;
+        }
+        Some(Self {
+            posthog,
+            sentry,
+            distinct_id,
+            context,
+            breadcrumbs,
+            scrub,
+        })
+    }
+
+    /// Reports the panic and waits until it's sent, or [`PANIC_REPORT_TIMEOUT`] elapses.
+    /// The session isn't ended, the panic may still be caught.
+    pub(crate) fn report(&self, message: &str) {
+        let report = ErrorReport::from_panic(message, &Backtrace::capture());
+        let breadcrumbs = self.breadcrumbs.snapshot();
+
+        if let Some(client) = &self.sentry {
+            let hub = sentry::Hub::new(Some(client.clone()), Default::default());
+            hub.with_scope(
+                |scope| {
+                    self.context.apply_to_scope(scope, Map::new());
+                    breadcrumbs::apply_to_scope(&breadcrumbs, scope);
+                },
+                || hub.capture_event(report.to_sentry_event()),
+            );
+            if !client.flush(Some(PANIC_REPORT_TIMEOUT)) {
+                debug_log("Failed to report panic to Sentry: timed out");
+            }
+        }
+
+        if let Some(worker) = &self.posthog {
+            let mut exception = PostHogEvent::exception(&report, &self.distinct_id);
+            self.context.apply_to_event(&mut exception, Map::new());
+            breadcrumbs::apply_to_event(breadcrumbs, &mut exception);
+            self.scrub.scrub_map(exception.properties_mut());
+            if let Err(e) = worker.capture_blocking_timeout(exception, PANIC_REPORT_TIMEOUT) {
+                debug_log(&format!("Failed to report panic: {}", e));
+            }
+        }
+    }
+}
+
+/// Makes `reporter` report subsequent panics and returns its id, see [`clear_reporter`].
+/// The hook is installed on first use, and runs the previously installed hook after reporting.
+pub(crate) fn set_reporter(reporter: PanicReporter) -> u64 {
+    let id = NEXT_REPORTER_ID.fetch_add(1, Ordering::Relaxed);
+    if let Ok(mut current) = REPORTER.write() {
+        *current = Some((id, Arc::new(reporter)));
+    }
+    INSTALL_HOOK.call_once(|| {
+        let previous_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let reporter = REPORTER
+                .read()
+                .ok()
+                .and_then(|current| current.as_ref().map(|(_, reporter)| reporter.clone()));
+            if let Some(reporter) = reporter {
+                reporter.report(&panic_message(info));
+            }
+            previous_hook(info);
+        }));
+    });
+    id
+}
+
+/// Stops reporting panics if the current reporter is the one with the given id,
+/// releasing it. Reporters set later by other instances are left alone. The hook
+/// stays installed, but only runs the previously installed hook.
+pub(crate) fn clear_reporter(id: u64) {
+    if let Ok(mut current) = REPORTER.write() {
+        if matches!(*current, Some((current_id, _)) if current_id == id) {
+            *current = None;
+        }
+    }
+}
+
+/// Whether the reporter with the given id is the current one
+#[cfg(test)]
+fn is_current(id: u64) -> bool {
+    matches!(*REPORTER.read().unwrap(), Some((current_id, _)) if current_id == id)
+}
+
+fn panic_message(info: &std::panic::PanicHookInfo<'_>) -> String {
+    let payload = info.payload();
+    let message = if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.as_str()
+    } else {
+        "Box<dyn Any>"
+    };
+    match info.location() {
+        Some(location) => format!("{} at {}", message, location),
+        None => message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::breadcrumbs::Breadcrumb;
+    use crate::test_utils::{enable_telemetry, keys, posthog_telemetry, setup, MockTransport};
+    use crate::{
+        PlatformProperties, RetryPolicy, SecretString, SessionStatus, Severity, Telemetry,
+    };
+
+    fn reporter(reporting: PanicReporting, transport: &MockTransport) -> Option<PanicReporter> {
+        let worker = PostHogWorker::spawn(
+            SecretString::new("phc_fake_key"),
+            "https://posthog.test",
+            Arc::new(transport.clone()),
+            RetryPolicy::none(),
+        )
+        .unwrap();
+        let context = TelemetryContext::new("test-app", "1.0.0", None, &PlatformProperties::none());
+        let breadcrumbs = Arc::new(Breadcrumbs::default());
+        breadcrumbs.add(Breadcrumb::new("event", "deploy_started", Severity::Info));
+        PanicReporter::new(
+            reporting,
+            Some(worker),
+            None,
+            "instance".to_string(),
+            Arc::new(context),
+            breadcrumbs,
+            Arc::new(ScrubConfig::none().pattern("src/main.rs", "<main>")),
+        )
+    }
+
+    #[test]
+    fn test_report_panic() {
+        let transport = MockTransport::new(200);
+        let reporter = reporter(PanicReporting::PreferSentry, &transport).unwrap();
+
+        reporter.report("boom at src/main.rs:1:1");
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        let properties = &requests[0]["properties"];
+        assert_eq!(requests[0]["event"], "$exception");
+        assert_eq!(properties["$exception_level"], "fatal");
+        assert_eq!(properties["handled"], false);
+        assert_eq!(properties["$exception_list"][0]["type"], "panic");
+        assert_eq!(
+            properties["$exception_list"][0]["value"],
+            "boom at <main>:1:1"
+        );
+        assert_eq!(properties["app"], "test-app");
+        assert_eq!(properties["breadcrumbs"][0]["message"], "deploy_started");
+        // The panic may be caught, so the session goes on
+        assert!(reporter
+            .context
+            .end_session(SessionStatus::Unknown, "instance")
+            .is_some());
+    }
+
+    #[test]
+    fn test_reporter_belongs_to_its_instance() {
+        let transport = MockTransport::new(200);
+        assert!(reporter(PanicReporting::Disabled, &transport).is_none());
+
+        let first = set_reporter(reporter(PanicReporting::AllBackends, &transport).unwrap());
+        let second = set_reporter(reporter(PanicReporting::AllBackends, &transport).unwrap());
+        // Only the instance that set the current reporter can clear it
+        clear_reporter(first);
+        assert!(is_current(second));
+        // Building an instance with reporting disabled leaves it alone too
+        let (_temp_dir, config_path) = setup();
+        drop(posthog_telemetry(&config_path, transport.clone()));
+        assert!(is_current(second));
+        clear_reporter(second);
+        assert!(REPORTER.read().unwrap().is_none());
+
+        enable_telemetry(&config_path);
+        let telemetry = Telemetry::builder("test-app", "1.0.0", "zksync-telemetry")
+            .keys(keys(Some("phc_fake_key"), None))
+            .custom_config_path(Some(config_path.into()))
+            .transport(transport)
+            .panic_reporting(PanicReporting::AllBackends)
+            .build()
+            .unwrap();
+        assert!(is_current(telemetry.panic_reporter.unwrap()));
+        // Dropping the instance releases its reporter and client
+        drop(telemetry);
+        assert!(REPORTER.read().unwrap().is_none());
+    }
+}
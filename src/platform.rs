@@ -0,0 +1,263 @@
+//! Optional properties describing the platform the app runs on.
+use once_cell::sync::OnceCell;
+use serde_json::{Map, Value};
+use std::io::IsTerminal;
+use std::process::Command;
+
+/// Selects the platform properties attached to every event and error report,
+/// in addition to the OS and CPU architecture reported by default.
+///
+/// All of them are disabled by default. Values are coarse so they can't identify a user:
+/// the OS version is the distribution and release number, the locale is only the
+/// language, and the terminal is only the program name.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PlatformProperties {
+    /// `os_version`, e.g. `ubuntu 22.04` or `macos 14.5`
+    pub os_version: bool,
+    /// `target`, the Rust target triple the app was built for
+    pub target: bool,
+    /// `rosetta` and `wsl`, whether the app runs under Rosetta 2 or WSL
+    pub emulation: bool,
+    /// `terminal`, e.g. `vscode` or `xterm-256color`, and `interactive`, whether stdout is a terminal
+    pub terminal: bool,
+    /// `locale`, the language of the user's locale, e.g. `en`
+    pub locale: bool,
+}
+
+impl PlatformProperties {
+    /// Doesn't collect any extra property
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// Collects every property
+    pub fn all() -> Self {
+        Self {
+            os_version: true,
+            target: true,
+            emulation: true,
+            terminal: true,
+            locale: true,
+        }
+    }
+
+    /// Returns the enabled properties, each detected once per process when first requested
+    pub(crate) fn collect(&self) -> Map<String, Value> {
+        self.collect_from(&PLATFORM_INFO)
+    }
+
+    fn collect_from(&self, info: &PlatformInfo) -> Map<String, Value> {
+        let mut properties = Map::new();
+        if *self == Self::none() {
+            return properties;
+        }
+        if self.os_version {
+            insert_some(&mut properties, "os_version", info.os_version().clone());
+        }
+        if self.target {
+            properties.insert("target".into(), env!("ZKSYNC_TELEMETRY_TARGET").into());
+        }
+        if self.emulation {
+            let emulation = info.emulation();
+            properties.insert("rosetta".into(), emulation.rosetta.into());
+            properties.insert("wsl".into(), emulation.wsl.into());
+        }
+        if self.terminal {
+            let terminal = info.terminal();
+            insert_some(&mut properties, "terminal", terminal.program.clone());
+            properties.insert("interactive".into(), terminal.interactive.into());
+        }
+        if self.locale {
+            insert_some(&mut properties, "locale", info.locale().clone());
+        }
+        properties
+    }
+}
+
+/// Detection may spawn processes, so nothing is detected until it's requested
+static PLATFORM_INFO: PlatformInfo = PlatformInfo::new();
+
+/// Platform information, each part detected on first use
+struct PlatformInfo {
+    os_version: OnceCell<Option<String>>,
+    emulation: OnceCell<Emulation>,
+    terminal: OnceCell<Terminal>,
+    locale: OnceCell<Option<String>>,
+}
+
+struct Emulation {
+    rosetta: bool,
+    wsl: bool,
+}
+
+struct Terminal {
+    program: Option<String>,
+    interactive: bool,
+}
+
+impl PlatformInfo {
+    const fn new() -> Self {
+        Self {
+            os_version: OnceCell::new(),
+            emulation: OnceCell::new(),
+            terminal: OnceCell::new(),
+            locale: OnceCell::new(),
+        }
+    }
+
+    fn os_version(&self) -> &Option<String> {
+        self.os_version.get_or_init(detect_os_version)
+    }
+
+    fn emulation(&self) -> &Emulation {
+        self.emulation.get_or_init(|| Emulation {
+            rosetta: cfg!(target_os = "macos")
+                && command_output("sysctl", &["-n", "sysctl.proc_translated"]).as_deref()
+                    == Some("1"),
+            wsl: cfg!(target_os = "linux")
+                && (std::env::var_os("WSL_DISTRO_NAME").is_some()
+                    || std::fs::read_to_string("/proc/sys/kernel/osrelease")
+                        .is_ok_and(|release| release.to_lowercase().contains("microsoft"))),
+        })
+    }
+
+    fn terminal(&self) -> &Terminal {
+        self.terminal.get_or_init(|| Terminal {
+            program: ["TERM_PROGRAM", "TERM"]
+                .iter()
+                .find_map(|var| std::env::var(var).ok().filter(|value| !value.is_empty())),
+            interactive: std::io::stdout().is_terminal(),
+        })
+    }
+
+    fn locale(&self) -> &Option<String> {
+        self.locale.get_or_init(|| {
+            ["LC_ALL", "LC_MESSAGES", "LANG"].iter().find_map(|var| {
+                std::env::var(var)
+                    .ok()
+                    .and_then(|value| locale_language(&value))
+            })
+        })
+    }
+}
+
+fn detect_os_version() -> Option<String> {
+    if cfg!(target_os = "linux") {
+        std::fs::read_to_string("/etc/os-release")
+            .ok()
+            .and_then(|os_release| parse_os_release(&os_release))
+    } else if cfg!(target_os = "macos") {
+        command_output("sw_vers", &["-productVersion"]).map(|version| format!("macos {}", version))
+    } else if cfg!(target_os = "windows") {
+        // e.g. "Microsoft Windows [Version 10.0.22631.3737]"
+        command_output("cmd", &["/C", "ver"]).and_then(|output| {
+            let version = output.split("Version ").nth(1)?.trim_end_matches(']');
+            Some(format!("windows {}", version))
+        })
+    } else {
+        None
+    }
+}
+
+/// Extracts `<ID> <VERSION_ID>` from the contents of `/etc/os-release`
+fn parse_os_release(os_release: &str) -> Option<String> {
+    let field = |name: &str| {
+        os_release.lines().find_map(|line| {
+            let value = line.strip_prefix(name)?.strip_prefix('=')?;
+            Some(value.trim_matches('"').to_string())
+        })
+    };
+    let id = field("ID")?;
+    Some(match field("VERSION_ID") {
+        Some(version) => format!("{} {}", id, version),
+        None => id,
+    })
+}
+
+/// Extracts the language from a POSIX locale such as `en_US.UTF-8`
+fn locale_language(locale: &str) -> Option<String> {
+    let language = locale.split(['_', '.', '@']).next()?;
+    (language.len() >= 2 && language.len() <= 3 && language.chars().all(|c| c.is_ascii_lowercase()))
+        .then(|| language.to_string())
+}
+
+pub(crate) fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|output| !output.is_empty())
+}
+
+fn insert_some(properties: &mut Map<String, Value>, key: &str, value: Option<String>) {
+    if let Some(value) = value {
+        properties.insert(key.into(), value.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect() {
+        assert!(PlatformProperties::none().collect().is_empty());
+
+        let properties = PlatformProperties {
+            target: true,
+            emulation: true,
+            ..PlatformProperties::none()
+        }
+        .collect();
+        assert!(properties["target"]
+            .as_str()
+            .unwrap()
+            .contains(std::env::consts::ARCH));
+        assert!(properties["rosetta"].is_boolean());
+        assert!(properties["wsl"].is_boolean());
+        assert!(properties.get("locale").is_none());
+    }
+
+    #[test]
+    fn test_only_requested_properties_are_detected() {
+        let info = PlatformInfo::new();
+        assert!(PlatformProperties::none().collect_from(&info).is_empty());
+        assert!(info.os_version.get().is_none());
+        assert!(info.emulation.get().is_none());
+        assert!(info.terminal.get().is_none());
+        assert!(info.locale.get().is_none());
+
+        let properties = PlatformProperties {
+            target: true,
+            locale: true,
+            ..PlatformProperties::none()
+        }
+        .collect_from(&info);
+        assert!(properties.contains_key("target"));
+        assert!(info.locale.get().is_some());
+        assert!(info.os_version.get().is_none());
+        assert!(info.emulation.get().is_none());
+        assert!(info.terminal.get().is_none());
+    }
+
+    #[test]
+    fn test_parse_os_release() {
+        let os_release = "NAME=\"Ubuntu\"\nVERSION_ID=\"22.04\"\nID=ubuntu\nID_LIKE=debian\n";
+        assert_eq!(
+            parse_os_release(os_release).as_deref(),
+            Some("ubuntu 22.04")
+        );
+        assert_eq!(parse_os_release("ID=arch\n").as_deref(), Some("arch"));
+        assert_eq!(parse_os_release("NAME=Unknown\n"), None);
+    }
+
+    #[test]
+    fn test_locale_language() {
+        assert_eq!(locale_language("en_US.UTF-8").as_deref(), Some("en"));
+        assert_eq!(locale_language("de_DE@euro").as_deref(), Some("de"));
+        assert_eq!(locale_language("fil").as_deref(), Some("fil"));
+        assert_eq!(locale_language("C.UTF-8"), None);
+        assert_eq!(locale_language("POSIX"), None);
+    }
+}
@@ -0,0 +1,104 @@
+//! Events in the format of the PostHog capture API.
+use crate::error::TelemetryResult;
+use crate::error_report::ErrorReport;
+use crate::severity::Severity;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+pub(crate) const DEFAULT_HOST: &str = "https://us.i.posthog.com";
+
+#[derive(Debug, Serialize)]
+pub(crate) struct PostHogEvent {
+    event: String,
+    distinct_id: String,
+    properties: Map<String, Value>,
+    timestamp: chrono::DateTime<chrono::Utc>,
+}
+
+/// Body of a capture request
+#[derive(Serialize)]
+struct CapturePayload<'a> {
+    api_key: &'a str,
+    #[serde(flatten)]
+    event: &'a PostHogEvent,
+}
+
+impl PostHogEvent {
+    pub(crate) fn new(event: &str, distinct_id: &str) -> Self {
+        Self {
+            event: event.to_string(),
+            distinct_id: distinct_id.to_string(),
+            properties: Map::new(),
+            timestamp: chrono::Utc::now(),
+        }
+    }
+
+    /// Creates a `$exception` event for an error, listing its chain
+    /// from the outermost error to the root cause, or for a message
+    pub(crate) fn exception(report: &ErrorReport, distinct_id: &str) -> Self {
+        if let Some(message) = report.message() {
+            // Messages are reported as synthetic exceptions, to be listed with errors
+            let exception = json!({
+                "type": "Message",
+                "value": message,
+                "mechanism": {
+                    "handled": true,
+                    "synthetic": true,
+                },
+            });
+            return Self::exception_from_list(
+                vec![exception],
+                report.severity(),
+                report.handled(),
+                distinct_id,
+            );
+        }
+        let exceptions = report
+            .exceptions()
+            .iter()
+            .rev()
+            .map(|exception| {
+                let mut exception = json!(exception);
+                exception["mechanism"] = mechanism(report.handled());
+                exception
+            })
+            .collect();
+        Self::exception_from_list(exceptions, report.severity(), report.handled(), distinct_id)
+    }
+
+    fn exception_from_list(
+        exceptions: Vec<Value>,
+        severity: Severity,
+        handled: bool,
+        distinct_id: &str,
+    ) -> Self {
+        let mut event = Self::new("$exception", distinct_id);
+        event.insert_prop("$exception_list", exceptions);
+        event.insert_prop("$exception_level", severity.as_str());
+        event.insert_prop("handled", handled);
+        event
+    }
+
+    pub(crate) fn insert_prop(&mut self, key: impl Into<String>, value: impl Into<Value>) {
+        self.properties.insert(key.into(), value.into());
+    }
+
+    pub(crate) fn properties_mut(&mut self) -> &mut Map<String, Value> {
+        &mut self.properties
+    }
+
+    /// Serializes the event into a capture request body
+    pub(crate) fn to_payload(&self, api_key: &str) -> TelemetryResult<Vec<u8>> {
+        Ok(serde_json::to_vec(&CapturePayload {
+            api_key,
+            event: self,
+        })?)
+    }
+}
+
+fn mechanism(handled: bool) -> Value {
+    json!({
+        "handled": handled,
+        "synthetic": false,
+    })
+}
@@ -37,6 +37,12 @@ impl TelemetryProps {
         }
     }
 
+    pub fn from_map(map: Map<String, Value>) -> Self {
+        Self {
+            inner: Value::Object(map),
+        }
+    }
+
     pub fn from_array<T: Into<TelemetryProps>>(values: Vec<T>) -> Self {
         Self {
             inner: Value::Array(values.into_iter().map(|v| v.into().inner).collect()),
@@ -0,0 +1,343 @@
+//! Client-side rate limiting of events and deduplication of repeated errors.
+use crate::error_report::ErrorReport;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Upper bound on remembered error fingerprints, expired ones are pruned beyond it
+const MAX_TRACKED_ERRORS: usize = 1000;
+
+/// Token bucket parameters: `burst` reports can be sent at once, after which
+/// they're refilled at `per_second`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quota {
+    pub per_second: f64,
+    pub burst: u32,
+}
+
+/// Limits how much telemetry a single process can send.
+///
+/// Reports exceeding a quota are dropped. Identical errors (same type and message)
+/// within `dedup_window` are reported once. Repetitions are counted and reported once
+/// the window expires, or when the instance is flushed or dropped: either with the next
+/// occurrence of the error, or by resending the error. The report carries the number
+/// of occurrences since the previous report.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateLimitConfig {
+    /// Quota for events tracked with `track_event`
+    pub event_quota: Option<Quota>,
+    /// Quota for error reports, applied after deduplication
+    pub error_quota: Option<Quota>,
+    pub dedup_window: Option<Duration>,
+}
+
+impl RateLimitConfig {
+    /// Sends everything
+    pub fn unlimited() -> Self {
+        Self {
+            event_quota: None,
+            error_quota: None,
+            dedup_window: None,
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            event_quota: Some(Quota {
+                per_second: 10.0,
+                burst: 100,
+            }),
+            error_quota: Some(Quota {
+                per_second: 1.0,
+                burst: 10,
+            }),
+            dedup_window: Some(Duration::from_secs(60)),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    quota: Quota,
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(quota: Quota, now: Instant) -> Self {
+        Self {
+            quota,
+            tokens: quota.burst as f64,
+            updated_at: now,
+        }
+    }
+
+    fn try_take(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.quota.per_second).min(self.quota.burst as f64);
+        self.updated_at = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Debug)]
+struct SeenError {
+    /// Resent with the number of suppressed occurrences if the error doesn't recur
+    report: ErrorReport,
+    reported_at: Instant,
+    /// Occurrences since the last report
+    suppressed: u64,
+}
+
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    events: Option<Mutex<TokenBucket>>,
+    errors: Option<Mutex<TokenBucket>>,
+    dedup_window: Option<Duration>,
+    seen_errors: Mutex<HashMap<String, SeenError>>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(config: RateLimitConfig) -> Self {
+        let now = Instant::now();
+        Self {
+            events: config
+                .event_quota
+                .map(|quota| Mutex::new(TokenBucket::new(quota, now))),
+            errors: config
+                .error_quota
+                .map(|quota| Mutex::new(TokenBucket::new(quota, now))),
+            dedup_window: config.dedup_window,
+            seen_errors: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns whether an event may be sent
+    pub(crate) fn allow_event(&self) -> bool {
+        self.allow_event_at(Instant::now())
+    }
+
+    /// Returns the number of occurrences to report for the error,
+    /// or `None` if it must not be reported
+    pub(crate) fn check_error(&self, report: &ErrorReport) -> Option<u64> {
+        self.check_error_at(report, Instant::now())
+    }
+
+    /// Returns the reported errors that occurred again since their last report, with
+    /// the number of these occurrences, and resets their count. Unless `expired_only`
+    /// is `false`, only errors whose deduplication window has expired are returned.
+    ///
+    /// The reports aren't subject to the error quota: there is at most one per
+    /// window for each error that passed it.
+    pub(crate) fn take_suppressed_errors(&self, expired_only: bool) -> Vec<(ErrorReport, u64)> {
+        self.take_suppressed_errors_at(expired_only, Instant::now())
+    }
+
+    fn allow_event_at(&self, now: Instant) -> bool {
+        take_token(&self.events, now)
+    }
+
+    fn check_error_at(&self, report: &ErrorReport, now: Instant) -> Option<u64> {
+        let fingerprint = report.fingerprint();
+        let Some(window) = self.dedup_window else {
+            return take_token(&self.errors, now).then_some(1);
+        };
+
+        let mut seen_errors = self.seen_errors.lock().ok()?;
+        if let Some(seen) = seen_errors.get_mut(fingerprint) {
+            if now.saturating_duration_since(seen.reported_at) < window {
+                seen.suppressed += 1;
+                return None;
+            }
+        }
+        if !take_token(&self.errors, now) {
+            // Keep counting, the occurrence is included in the next report
+            if let Some(seen) = seen_errors.get_mut(fingerprint) {
+                seen.suppressed += 1;
+            }
+            return None;
+        }
+
+        if seen_errors.len() >= MAX_TRACKED_ERRORS {
+            seen_errors.retain(|_, seen| now.saturating_duration_since(seen.reported_at) < window);
+        }
+        let suppressed = seen_errors
+            .insert(
+                fingerprint.to_string(),
+                SeenError {
+                    report: report.clone(),
+                    reported_at: now,
+                    suppressed: 0,
+                },
+            )
+            .map_or(0, |seen| seen.suppressed);
+        Some(suppressed + 1)
+    }
+
+    fn take_suppressed_errors_at(
+        &self,
+        expired_only: bool,
+        now: Instant,
+    ) -> Vec<(ErrorReport, u64)> {
+        let (Some(window), Ok(mut seen_errors)) = (self.dedup_window, self.seen_errors.lock())
+        else {
+            return Vec::new();
+        };
+        seen_errors
+            .values_mut()
+            .filter(|seen| {
+                seen.suppressed > 0
+                    && (!expired_only || now.saturating_duration_since(seen.reported_at) >= window)
+            })
+            .map(|seen| {
+                seen.reported_at = now;
+                (seen.report.clone(), std::mem::take(&mut seen.suppressed))
+            })
+            .collect()
+    }
+}
+
+fn take_token(bucket: &Option<Mutex<TokenBucket>>, now: Instant) -> bool {
+    match bucket {
+        Some(bucket) => bucket.lock().is_ok_and(|mut bucket| bucket.try_take(now)),
+        None => true,
+    }
+}
+
+/// Identifies errors that are considered identical for deduplication
+pub(crate) fn error_fingerprint(error: &dyn std::error::Error) -> String {
+    let debug = format!("{:?}", error);
+    format!("{}: {}", sentry::parse_type_from_debug(&debug), error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(message: &str) -> ErrorReport {
+        ErrorReport::from_message(message)
+    }
+
+    #[test]
+    fn test_event_quota() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            event_quota: Some(Quota {
+                per_second: 2.0,
+                burst: 3,
+            }),
+            ..RateLimitConfig::unlimited()
+        });
+        let now = Instant::now();
+
+        assert_eq!((0..5).filter(|_| limiter.allow_event_at(now)).count(), 3);
+        assert!(limiter.allow_event_at(now + Duration::from_millis(500)));
+        assert!(!limiter.allow_event_at(now + Duration::from_millis(500)));
+        // Refilled tokens never exceed the burst
+        let later = now + Duration::from_secs(60);
+        assert_eq!((0..5).filter(|_| limiter.allow_event_at(later)).count(), 3);
+
+        let unlimited = RateLimiter::new(RateLimitConfig::unlimited());
+        assert!((0..1000).all(|_| unlimited.allow_event_at(now)));
+    }
+
+    #[test]
+    fn test_error_dedup() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            dedup_window: Some(Duration::from_secs(10)),
+            ..RateLimitConfig::unlimited()
+        });
+        let now = Instant::now();
+
+        assert_eq!(limiter.check_error_at(&report("a"), now), Some(1));
+        assert_eq!(limiter.check_error_at(&report("b"), now), Some(1));
+        for _ in 0..5 {
+            assert_eq!(
+                limiter.check_error_at(&report("a"), now + Duration::from_secs(1)),
+                None
+            );
+        }
+
+        // After the window, the report includes the suppressed occurrences
+        let later = now + Duration::from_secs(11);
+        assert_eq!(limiter.check_error_at(&report("a"), later), Some(6));
+        assert_eq!(limiter.check_error_at(&report("b"), later), Some(1));
+        assert_eq!(limiter.check_error_at(&report("a"), later), None);
+    }
+
+    #[test]
+    fn test_suppressed_errors() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            error_quota: Some(Quota {
+                per_second: 1.0,
+                burst: 1,
+            }),
+            dedup_window: Some(Duration::from_secs(10)),
+            ..RateLimitConfig::unlimited()
+        });
+        let now = Instant::now();
+
+        assert_eq!(limiter.check_error_at(&report("a"), now), Some(1));
+        for _ in 0..99 {
+            assert_eq!(limiter.check_error_at(&report("a"), now), None);
+        }
+        assert!(limiter.take_suppressed_errors_at(true, now).is_empty());
+
+        // Reported once the window expires, regardless of the quota
+        let later = now + Duration::from_secs(10);
+        let suppressed = limiter.take_suppressed_errors_at(true, later);
+        assert_eq!(suppressed.len(), 1);
+        assert_eq!(suppressed[0].0.fingerprint(), "message: a");
+        assert_eq!(suppressed[0].1, 99);
+        assert!(limiter.take_suppressed_errors_at(false, later).is_empty());
+
+        // Taking all of them doesn't wait for the window, and restarts it
+        assert_eq!(limiter.check_error_at(&report("a"), later), None);
+        let suppressed = limiter.take_suppressed_errors_at(false, later);
+        assert_eq!(suppressed[0].1, 1);
+        let even_later = later + Duration::from_secs(1);
+        assert_eq!(limiter.check_error_at(&report("a"), even_later), None);
+    }
+
+    #[test]
+    fn test_error_quota() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            error_quota: Some(Quota {
+                per_second: 1.0,
+                burst: 1,
+            }),
+            dedup_window: Some(Duration::from_secs(10)),
+            ..RateLimitConfig::unlimited()
+        });
+        let now = Instant::now();
+
+        assert_eq!(limiter.check_error_at(&report("a"), now), Some(1));
+        assert_eq!(limiter.check_error_at(&report("b"), now), None);
+        // Dropped occurrences of a reported error are counted
+        let later = now + Duration::from_secs(11);
+        assert_eq!(limiter.check_error_at(&report("b"), later), Some(1));
+        assert_eq!(limiter.check_error_at(&report("a"), later), None);
+        let even_later = later + Duration::from_secs(1);
+        assert_eq!(limiter.check_error_at(&report("a"), even_later), Some(2));
+    }
+
+    #[test]
+    fn test_error_fingerprint() {
+        let error = std::io::Error::other("disk full");
+        assert_eq!(
+            error_fingerprint(&error),
+            error_fingerprint(&std::io::Error::other("disk full"))
+        );
+        assert_ne!(
+            error_fingerprint(&error),
+            error_fingerprint(&std::io::Error::other("disk empty"))
+        );
+        assert!(error_fingerprint(&error).ends_with(": disk full"));
+    }
+}
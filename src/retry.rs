@@ -0,0 +1,212 @@
+//! Retry policy for delivering events to PostHog.
+use crate::error::{TelemetryError, TelemetryResult};
+use crate::transport::{Transport, TransportRequest, TransportResponse};
+use std::time::{Duration, Instant};
+
+/// Statuses worth retrying: timeouts, rate limiting and transient server errors
+const RETRYABLE_STATUSES: [u16; 6] = [408, 429, 500, 502, 503, 504];
+
+/// Controls how failed sends are retried.
+///
+/// Delays grow exponentially from `initial_backoff` up to `max_backoff`, with
+/// random jitter. A `Retry-After` header sent by the server takes precedence.
+/// No retry is started if its delay would exceed `max_elapsed`. Attempts in progress
+/// aren't interrupted, so delivering one event can take up to `max_elapsed` plus the
+/// request timeout of the transport.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt
+    pub max_retries: u32,
+    /// Delay before the first retry
+    pub initial_backoff: Duration,
+    /// Upper bound for a single delay
+    pub max_backoff: Duration,
+    /// Time after the first attempt of an event within which retries can start
+    pub max_elapsed: Duration,
+}
+
+impl RetryPolicy {
+    /// Sends every event exactly once
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Delay before retry number `retry` (starting at 0), before jitter
+    fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+            max_elapsed: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Sends the request, retrying transport errors and retryable statuses
+/// according to `policy`. Returns the last response or error.
+pub(crate) fn send_with_retry(
+    transport: &dyn Transport,
+    request: &TransportRequest,
+    policy: &RetryPolicy,
+) -> TelemetryResult<TransportResponse> {
+    let started = Instant::now();
+    let mut retry = 0;
+    loop {
+        let result = transport.send(request);
+        let retry_after = match &result {
+            Ok(response) if RETRYABLE_STATUSES.contains(&response.status) => response.retry_after,
+            Err(TelemetryError::SendError(_)) => None,
+            _ => return result,
+        };
+        if retry >= policy.max_retries {
+            return result;
+        }
+
+        let delay = retry_after.unwrap_or_else(|| with_jitter(policy.backoff(retry)));
+        if started.elapsed() + delay > policy.max_elapsed {
+            return result;
+        }
+        std::thread::sleep(delay);
+        retry += 1;
+    }
+}
+
+/// Picks a random delay between half and all of `delay`
+fn with_jitter(delay: Duration) -> Duration {
+    delay.mul_f64(0.5 + fastrand::f64() * 0.5)
+}
+
+/// Parses a `Retry-After` header given either in seconds or as an HTTP date
+pub(crate) fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+        .to_std()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Replies with the given results in order
+    struct ScriptedTransport {
+        results: Mutex<Vec<TelemetryResult<TransportResponse>>>,
+        attempts: Mutex<u32>,
+    }
+
+    impl ScriptedTransport {
+        fn new(mut results: Vec<TelemetryResult<TransportResponse>>) -> Self {
+            results.reverse();
+            Self {
+                results: Mutex::new(results),
+                attempts: Mutex::new(0),
+            }
+        }
+    }
+
+    impl Transport for ScriptedTransport {
+        fn send(&self, _: &TransportRequest) -> TelemetryResult<TransportResponse> {
+            *self.attempts.lock().unwrap() += 1;
+            self.results.lock().unwrap().pop().unwrap()
+        }
+    }
+
+    fn status(status: u16) -> TelemetryResult<TransportResponse> {
+        Ok(TransportResponse {
+            status,
+            retry_after: None,
+        })
+    }
+
+    fn fast_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(5),
+            max_elapsed: Duration::from_secs(5),
+        }
+    }
+
+    fn request() -> TransportRequest {
+        TransportRequest {
+            url: "https://example.com/capture/".to_string(),
+            body: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_retries_transient_failures() {
+        let transport = ScriptedTransport::new(vec![
+            status(502),
+            Err(TelemetryError::SendError("connection reset".to_string())),
+            status(200),
+        ]);
+        let response = send_with_retry(&transport, &request(), &fast_policy()).unwrap();
+
+        assert_eq!(response.status, 200);
+        assert_eq!(*transport.attempts.lock().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_gives_up() {
+        // Client errors are not retried
+        let transport = ScriptedTransport::new(vec![status(400)]);
+        let response = send_with_retry(&transport, &request(), &fast_policy()).unwrap();
+        assert_eq!(response.status, 400);
+        assert_eq!(*transport.attempts.lock().unwrap(), 1);
+
+        // Retries are limited
+        let transport = ScriptedTransport::new((0..4).map(|_| status(503)).collect());
+        let response = send_with_retry(&transport, &request(), &fast_policy()).unwrap();
+        assert_eq!(response.status, 503);
+        assert_eq!(*transport.attempts.lock().unwrap(), 4);
+
+        // A Retry-After beyond the time budget stops retrying
+        let transport = ScriptedTransport::new(vec![Ok(TransportResponse {
+            status: 429,
+            retry_after: Some(Duration::from_secs(60)),
+        })]);
+        let response = send_with_retry(&transport, &request(), &fast_policy()).unwrap();
+        assert_eq!(response.status, 429);
+        assert_eq!(*transport.attempts.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(2), Duration::from_secs(2));
+        assert_eq!(policy.backoff(10), Duration::from_secs(10));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(10));
+
+        let delay = with_jitter(Duration::from_secs(2));
+        assert!(delay >= Duration::from_secs(1) && delay <= Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), None);
+        assert_eq!(parse_retry_after("soon"), None);
+
+        let date = (chrono::Utc::now() + chrono::Duration::seconds(90)).to_rfc2822();
+        let delay = parse_retry_after(&date).unwrap();
+        assert!(delay > Duration::from_secs(80) && delay <= Duration::from_secs(90));
+    }
+}
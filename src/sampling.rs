@@ -0,0 +1,103 @@
+//! Client-side sampling of events by name.
+use std::collections::HashMap;
+
+/// Fraction of events to send, configurable per event name.
+///
+/// Only events tracked with `track_event` are sampled, error reports are always sent.
+/// Every sent event carries the applied rate in its `sample_rate` property, so
+/// counts can be reweighted by `1 / sample_rate`.
+///
+/// ```
+/// use zksync_telemetry::SamplingConfig;
+///
+/// // Send 1% of RPC calls and 50% of everything else
+/// let sampling = SamplingConfig::new()
+///     .default_rate(0.5)
+///     .event_rate("rpc_call", 0.01);
+/// assert_eq!(sampling.rate_for("rpc_call"), 0.01);
+/// assert_eq!(sampling.rate_for("command_executed"), 0.5);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct SamplingConfig {
+    default_rate: f64,
+    rates: HashMap<String, f64>,
+}
+
+impl SamplingConfig {
+    /// Creates a config that sends every event
+    pub fn new() -> Self {
+        Self {
+            default_rate: 1.0,
+            rates: HashMap::new(),
+        }
+    }
+
+    /// Sets the rate for events without a specific rate. Clamped to `0.0..=1.0`.
+    pub fn default_rate(mut self, rate: f64) -> Self {
+        self.default_rate = clamp_rate(rate);
+        self
+    }
+
+    /// Sets the rate for events named `event_name`. Clamped to `0.0..=1.0`.
+    pub fn event_rate(mut self, event_name: impl Into<String>, rate: f64) -> Self {
+        self.rates.insert(event_name.into(), clamp_rate(rate));
+        self
+    }
+
+    /// Returns the rate applied to events named `event_name`
+    pub fn rate_for(&self, event_name: &str) -> f64 {
+        self.rates
+            .get(event_name)
+            .copied()
+            .unwrap_or(self.default_rate)
+    }
+
+    /// Decides whether to send the event. Returns the applied rate if so.
+    pub(crate) fn sample(&self, event_name: &str) -> Option<f64> {
+        let rate = self.rate_for(event_name);
+        (rate >= 1.0 || fastrand::f64() < rate).then_some(rate)
+    }
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn clamp_rate(rate: f64) -> f64 {
+    if rate.is_nan() {
+        1.0
+    } else {
+        rate.clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sampling() {
+        let sampling = SamplingConfig::new()
+            .event_rate("dropped", 0.0)
+            .event_rate("invalid", f64::NAN)
+            .event_rate("sampled", 0.5)
+            .event_rate("too_high", 3.0);
+
+        assert_eq!(sampling.sample("other"), Some(1.0));
+        assert_eq!(sampling.sample("dropped"), None);
+        assert_eq!(sampling.sample("invalid"), Some(1.0));
+        assert_eq!(sampling.sample("too_high"), Some(1.0));
+
+        let sent = (0..1000)
+            .filter_map(|_| sampling.sample("sampled"))
+            .inspect(|rate| assert_eq!(*rate, 0.5))
+            .count();
+        assert!((350..650).contains(&sent));
+
+        let sampling = sampling.default_rate(-1.0);
+        assert_eq!(sampling.sample("other"), None);
+        assert_eq!(sampling.rate_for("sampled"), 0.5);
+    }
+}
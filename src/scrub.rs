@@ -0,0 +1,365 @@
+//! Scrubbing of personal information from events and error reports.
+use crate::platform::command_output;
+use once_cell::sync::Lazy;
+use sentry::protocol::{Event, Frame};
+use serde_json::{Map, Value};
+
+/// Shortest username or hostname scrubbed, shorter ones would match ordinary words
+const MIN_NAME_LENGTH: usize = 4;
+
+/// Usernames and hostnames shared by many machines, which identify no one and
+/// are ordinary words in messages, e.g. `state root mismatch`
+const SYSTEM_NAMES: &[&str] = &[
+    "admin",
+    "administrator",
+    "builder",
+    "default",
+    "docker",
+    "guest",
+    "host",
+    "localhost",
+    "node",
+    "root",
+    "runner",
+    "server",
+    "ubuntu",
+    "user",
+    "vagrant",
+];
+
+/// Replaces personal information in error messages, stack frames and properties
+/// of events, error reports, panics and messages before they're sent to either backend.
+///
+/// By default, the home directory is replaced with `~`, and the username and
+/// hostname with `<user>` and `<hostname>` where they appear as whole words.
+/// More literal patterns can be added:
+///
+/// ```
+/// use zksync_telemetry::ScrubConfig;
+///
+/// let scrub = ScrubConfig::default().pattern("/srv/deployments", "<deployments>");
+/// ```
+#[derive(Clone, Debug)]
+pub struct ScrubConfig {
+    patterns: Vec<Replacement>,
+    /// Replacements of the home directory, hostname and username
+    identity: Option<&'static Lazy<Vec<Replacement>>>,
+}
+
+#[derive(Clone, Debug)]
+struct Replacement {
+    pattern: String,
+    placeholder: String,
+    /// Only replaces occurrences not surrounded by letters, digits or `_`
+    whole_word: bool,
+}
+
+impl ScrubConfig {
+    /// Doesn't scrub anything
+    pub fn none() -> Self {
+        Self {
+            patterns: Vec::new(),
+            identity: None,
+        }
+    }
+
+    /// Replaces every occurrence of `pattern` with `placeholder`.
+    /// Patterns are applied in the order they were added, before the defaults.
+    pub fn pattern(mut self, pattern: impl Into<String>, placeholder: impl Into<String>) -> Self {
+        let pattern = pattern.into();
+        if !pattern.is_empty() {
+            self.patterns
+                .push(Replacement::new(&pattern, &placeholder.into(), false));
+        }
+        self
+    }
+
+    /// Detects the home directory, hostname and username if they're scrubbed and
+    /// haven't been detected yet. Detection may spawn a process, so it's done when
+    /// a backend is configured rather than when the first panic is reported.
+    pub(crate) fn detect_identity(&self) {
+        if let Some(identity) = self.identity {
+            Lazy::force(identity);
+        }
+    }
+
+    pub(crate) fn scrub_str(&self, text: &str) -> String {
+        let identity = self.identity.map(|identity| identity.iter());
+        self.patterns
+            .iter()
+            .chain(identity.into_iter().flatten())
+            .fold(text.to_string(), |text, replacement| {
+                replacement.apply(&text)
+            })
+    }
+
+    /// Scrubs every string in `value`, recursively
+    pub(crate) fn scrub_value(&self, value: &mut Value) {
+        match value {
+            Value::String(string) => *string = self.scrub_str(string),
+            Value::Array(values) => values.iter_mut().for_each(|value| self.scrub_value(value)),
+            Value::Object(map) => self.scrub_map(map),
+            _ => {}
+        }
+    }
+
+    pub(crate) fn scrub_map(&self, map: &mut Map<String, Value>) {
+        map.values_mut().for_each(|value| self.scrub_value(value));
+    }
+
+    pub(crate) fn scrub_sentry_event(&self, mut event: Event<'static>) -> Event<'static> {
+        let scrub_option = |option: &mut Option<String>| {
+            if let Some(string) = option {
+                *string = self.scrub_str(string);
+            }
+        };
+        scrub_option(&mut event.message);
+        for exception in event.exception.values.iter_mut() {
+            scrub_option(&mut exception.value);
+            if let Some(stacktrace) = &mut exception.stacktrace {
+                stacktrace
+                    .frames
+                    .iter_mut()
+                    .for_each(|frame| self.scrub_frame(frame));
+            }
+        }
+        for value in event.tags.values_mut() {
+            *value = self.scrub_str(value);
+        }
+        for value in event.extra.values_mut() {
+            self.scrub_value(value);
+        }
+        for breadcrumb in event.breadcrumbs.values.iter_mut() {
+            scrub_option(&mut breadcrumb.message);
+            breadcrumb
+                .data
+                .values_mut()
+                .for_each(|value| self.scrub_value(value));
+        }
+        if let Some(server_name) = &mut event.server_name {
+            *server_name = self.scrub_str(server_name).into();
+        }
+        event
+    }
+
+    fn scrub_frame(&self, frame: &mut Frame) {
+        let fields = [
+            &mut frame.function,
+            &mut frame.filename,
+            &mut frame.abs_path,
+        ];
+        for string in fields.into_iter().flatten() {
+            *string = self.scrub_str(string);
+        }
+    }
+}
+
+impl Default for ScrubConfig {
+    fn default() -> Self {
+        Self {
+            patterns: Vec::new(),
+            identity: Some(&IDENTITY),
+        }
+    }
+}
+
+impl Replacement {
+    fn new(pattern: &str, placeholder: &str, whole_word: bool) -> Self {
+        Self {
+            pattern: pattern.to_string(),
+            placeholder: placeholder.to_string(),
+            whole_word,
+        }
+    }
+
+    fn apply(&self, text: &str) -> String {
+        if !self.whole_word {
+            return text.replace(&self.pattern, &self.placeholder);
+        }
+        let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+        let mut result = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(index) = rest.find(&self.pattern) {
+            let (before, after) = (&rest[..index], &rest[index + self.pattern.len()..]);
+            let previous = before.chars().next_back().or(result.chars().next_back());
+            let bounded = !previous.is_some_and(is_word_char)
+                && !after.chars().next().is_some_and(is_word_char);
+            result.push_str(before);
+            result.push_str(if bounded {
+                &self.placeholder
+            } else {
+                &self.pattern
+            });
+            rest = after;
+        }
+        result.push_str(rest);
+        result
+    }
+}
+
+/// Replacements of the personal information of the current user, detected once per process
+static IDENTITY: Lazy<Vec<Replacement>> = Lazy::new(|| Identity::detect().replacements());
+
+struct Identity {
+    home_dir: Option<String>,
+    username: Option<String>,
+    hostname: Option<String>,
+}
+
+impl Identity {
+    fn detect() -> Self {
+        let home_dir = directories::BaseDirs::new()
+            .map(|dirs| dirs.home_dir().to_string_lossy().into_owned())
+            .filter(|home_dir| home_dir.len() > 1);
+        let username = ["USER", "USERNAME", "LOGNAME"]
+            .iter()
+            .find_map(|var| std::env::var(var).ok().filter(|value| !value.is_empty()));
+        let hostname = ["HOSTNAME", "COMPUTERNAME"]
+            .iter()
+            .find_map(|var| std::env::var(var).ok().filter(|value| !value.is_empty()))
+            .or_else(|| {
+                std::fs::read_to_string("/etc/hostname")
+                    .ok()
+                    .map(|hostname| hostname.trim().to_string())
+                    .filter(|hostname| !hostname.is_empty())
+            })
+            .or_else(|| command_output("hostname", &[]));
+        Self {
+            home_dir,
+            username,
+            hostname,
+        }
+    }
+
+    fn replacements(self) -> Vec<Replacement> {
+        let mut replacements = Vec::new();
+        if let Some(home_dir) = &self.home_dir {
+            replacements.push(Replacement::new(home_dir, "~", false));
+        }
+        // Hostnames often contain the username, e.g. `alice-laptop`
+        for (name, placeholder) in [(&self.hostname, "<hostname>"), (&self.username, "<user>")] {
+            if let Some(name) = name.as_ref().filter(|name| is_personal_name(name)) {
+                replacements.push(Replacement::new(name, placeholder, true));
+            }
+        }
+        replacements
+    }
+}
+
+/// Whether `name` is long enough and not a well-known system name to be scrubbed
+fn is_personal_name(name: &str) -> bool {
+    name.len() >= MIN_NAME_LENGTH
+        && !SYSTEM_NAMES
+            .iter()
+            .any(|system_name| system_name.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    static TEST_IDENTITY: Lazy<Vec<Replacement>> = Lazy::new(|| {
+        Identity {
+            home_dir: Some("/home/alice".to_string()),
+            username: Some("alice".to_string()),
+            hostname: Some("alice-laptop".to_string()),
+        }
+        .replacements()
+    });
+
+    fn scrub() -> ScrubConfig {
+        ScrubConfig {
+            patterns: Vec::new(),
+            identity: Some(&TEST_IDENTITY),
+        }
+        .pattern("/srv/deployments", "<deployments>")
+    }
+
+    #[test]
+    fn test_scrub_str() {
+        let scrub = scrub();
+        assert_eq!(
+            scrub.scrub_str("failed to read /home/alice/projects/app/config.toml"),
+            "failed to read ~/projects/app/config.toml"
+        );
+        assert_eq!(
+            scrub.scrub_str("connection from alice@alice-laptop refused"),
+            "connection from <user>@<hostname> refused"
+        );
+        assert_eq!(scrub.scrub_str("malice and alicent"), "malice and alicent");
+        assert_eq!(
+            scrub.scrub_str("missing /srv/deployments/alice"),
+            "missing <deployments>/<user>"
+        );
+        assert_eq!(ScrubConfig::none().scrub_str("/home/alice"), "/home/alice");
+    }
+
+    #[test]
+    fn test_system_names_are_kept() {
+        static ROOT_IDENTITY: Lazy<Vec<Replacement>> = Lazy::new(|| {
+            Identity {
+                home_dir: Some("/root".to_string()),
+                username: Some("root".to_string()),
+                hostname: Some("localhost".to_string()),
+            }
+            .replacements()
+        });
+        let scrub = ScrubConfig {
+            patterns: Vec::new(),
+            identity: Some(&ROOT_IDENTITY),
+        };
+        assert_eq!(
+            scrub.scrub_str("state root mismatch on localhost, see /root/app.log"),
+            "state root mismatch on localhost, see ~/app.log"
+        );
+    }
+
+    #[test]
+    fn test_scrub_value() {
+        let mut value = json!({
+            "path": "/home/alice/app",
+            "nested": [{ "owner": "alice" }],
+            "count": 3,
+        });
+        scrub().scrub_value(&mut value);
+        assert_eq!(
+            value,
+            json!({
+                "path": "~/app",
+                "nested": [{ "owner": "<user>" }],
+                "count": 3,
+            })
+        );
+    }
+
+    #[test]
+    fn test_scrub_sentry_event() {
+        let mut event = sentry::event_from_error(&std::io::Error::other(
+            "failed to read /home/alice/config.toml",
+        ));
+        event.exception.values[0].stacktrace = Some(sentry::protocol::Stacktrace {
+            frames: vec![Frame {
+                function: Some("app::main".to_string()),
+                abs_path: Some("/home/alice/app/src/main.rs".to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+        event.tags.insert("owner".to_string(), "alice".to_string());
+
+        let event = scrub().scrub_sentry_event(event);
+        let exception = &event.exception.values[0];
+        assert_eq!(
+            exception.value.as_deref(),
+            Some("failed to read ~/config.toml")
+        );
+        assert_eq!(
+            exception.stacktrace.as_ref().unwrap().frames[0]
+                .abs_path
+                .as_deref(),
+            Some("~/app/src/main.rs")
+        );
+        assert_eq!(event.tags["owner"], "<user>");
+    }
+}
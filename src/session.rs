@@ -0,0 +1,73 @@
+//! Sessions correlating the events of one app run.
+use once_cell::sync::Lazy;
+use std::time::{Duration, Instant};
+
+/// Session used until the app starts its own, shared by all instances in the process
+static PROCESS_SESSION_ID: Lazy<String> = Lazy::new(|| uuid::Uuid::new_v4().to_string());
+
+/// How a session ended, reported as the `exit_status` property of `session_ended`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionStatus {
+    Success,
+    Failure,
+    Crashed,
+    /// The session was replaced or the telemetry instance dropped without an explicit end
+    Unknown,
+}
+
+impl SessionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionStatus::Success => "success",
+            SessionStatus::Failure => "failure",
+            SessionStatus::Crashed => "crashed",
+            SessionStatus::Unknown => "unknown",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Session {
+    pub(crate) id: String,
+    started_at: Instant,
+    ended: bool,
+}
+
+impl Session {
+    /// Starts the session of the current process
+    pub(crate) fn process() -> Self {
+        Self::new(PROCESS_SESSION_ID.clone())
+    }
+
+    pub(crate) fn new(id: String) -> Self {
+        Self {
+            id,
+            started_at: Instant::now(),
+            ended: false,
+        }
+    }
+
+    /// Marks the session as ended and returns its duration,
+    /// or `None` if it has already ended
+    pub(crate) fn end(&mut self) -> Option<Duration> {
+        if self.ended {
+            return None;
+        }
+        self.ended = true;
+        Some(self.started_at.elapsed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session() {
+        assert_eq!(Session::process().id, Session::process().id);
+
+        let mut session = Session::new("custom".to_string());
+        assert!(session.end().is_some());
+        assert!(session.end().is_none());
+    }
+}
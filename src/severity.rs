@@ -0,0 +1,39 @@
+//! Severity of error reports and breadcrumbs.
+use serde::Serialize;
+
+/// How serious a reported error is, from least to most severe.
+/// Sent as the Sentry event level and as `$exception_level` to PostHog.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Debug,
+    Info,
+    /// An expected or recoverable failure
+    Warning,
+    #[default]
+    Error,
+    /// A failure the app can't recover from, e.g. a panic
+    Fatal,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Debug => "debug",
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+            Severity::Fatal => "fatal",
+        }
+    }
+
+    pub(crate) fn to_sentry(self) -> sentry::Level {
+        match self {
+            Severity::Debug => sentry::Level::Debug,
+            Severity::Info => sentry::Level::Info,
+            Severity::Warning => sentry::Level::Warning,
+            Severity::Error => sentry::Level::Error,
+            Severity::Fatal => sentry::Level::Fatal,
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! Timing spans reporting the duration and outcome of an operation.
+use crate::{ErrorReport, Telemetry, TelemetryProps};
+use std::time::Instant;
+
+/// How the operation measured by a [`Span`] ended
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SpanOutcome {
+    Success,
+    /// The operation failed with an error of the given kind, e.g. `DeploymentError`
+    Failure {
+        error_kind: String,
+    },
+}
+
+impl SpanOutcome {
+    /// Failure with the type of the outermost error as the error kind, the same
+    /// type as in its error report. Unlike the message of the error, it can't
+    /// contain user data. Accepts anything convertible to an [`ErrorReport`],
+    /// including boxed errors and `anyhow::Error`.
+    pub fn from_error(error: impl Into<ErrorReport>) -> Self {
+        Self::from_report(&error.into())
+    }
+
+    /// Failure with the error kind of `report`, see [`SpanOutcome::from_error`]
+    pub fn from_report(report: &ErrorReport) -> Self {
+        Self::Failure {
+            error_kind: report.kind().to_string(),
+        }
+    }
+}
+
+/// Measures an operation and tracks it as a single event, see [`Telemetry::start_span`].
+///
+/// The event is named after the span and has the properties added to the span, plus:
+/// - `duration_ms`: time from the start of the span until it ended
+/// - `success`: whether the operation succeeded
+/// - `outcome`: `success`, `failure`, `panic`, or `abandoned` if the span was dropped
+///   without being finished, e.g. on an early return
+/// - `error_kind`: kind of the error, for failures and panics
+#[must_use = "the span is reported when dropped"]
+pub struct Span<'a> {
+    telemetry: &'a Telemetry,
+    name: String,
+    started_at: Instant,
+    properties: TelemetryProps,
+    finished: bool,
+}
+
+impl<'a> Span<'a> {
+    pub(crate) fn new(telemetry: &'a Telemetry, name: &str) -> Self {
+        Self {
+            telemetry,
+            name: name.to_string(),
+            started_at: Instant::now(),
+            properties: TelemetryProps::new(),
+            finished: false,
+        }
+    }
+
+    /// Adds a property to the event
+    pub fn insert(&mut self, key: impl ToString, value: impl Into<TelemetryProps>) -> &mut Self {
+        self.properties.insert(key, Some(value));
+        self
+    }
+
+    /// Ends the span and queues its event
+    pub fn finish(mut self, outcome: SpanOutcome) {
+        match outcome {
+            SpanOutcome::Success => self.report("success", None),
+            SpanOutcome::Failure { error_kind } => self.report("failure", Some(&error_kind)),
+        }
+    }
+
+    /// Ends the span with the outcome of `result`. For results that can't be borrowed
+    /// as an error, such as `anyhow::Result`, use [`SpanOutcome::from_error`] with
+    /// `&**error`.
+    pub fn finish_result<T, E>(self, result: &Result<T, E>)
+    where
+        for<'e> &'e E: Into<ErrorReport>,
+    {
+        let outcome = match result {
+            Ok(_) => SpanOutcome::Success,
+            Err(error) => SpanOutcome::from_error(error),
+        };
+        self.finish(outcome);
+    }
+
+    fn report(&mut self, outcome: &str, error_kind: Option<&str>) {
+        let mut properties = self.properties.take();
+        properties
+            .insert(
+                "duration_ms",
+                Some(serde_json::Number::from(
+                    self.started_at.elapsed().as_millis() as u64,
+                )),
+            )
+            .insert("success", Some(outcome == "success"))
+            .insert("outcome", Some(outcome))
+            .insert("error_kind", error_kind);
+        self.telemetry.track_event_detached(&self.name, properties);
+        self.finished = true;
+    }
+}
+
+impl Drop for Span<'_> {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        if std::thread::panicking() {
+            self.report("panic", Some("panic"));
+        } else {
+            self.report("abandoned", None);
+        }
+    }
+}
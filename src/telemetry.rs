@@ -1,84 +1,112 @@
-use crate::{TelemetryConfig, TelemetryError, TelemetryProps, TelemetryResult};
+use crate::breadcrumbs::{self, Breadcrumb, Breadcrumbs};
+use crate::builder::TelemetryBuilder;
+use crate::context::TelemetryContext;
+use crate::diagnostics::TelemetryDiagnostics;
+use crate::error_report::ErrorReport;
+use crate::panic;
+use crate::platform::PlatformProperties;
+use crate::posthog::PostHogEvent;
+use crate::rate_limit::{RateLimitConfig, RateLimiter};
+use crate::sampling::SamplingConfig;
+use crate::scrub::ScrubConfig;
+use crate::session::SessionStatus;
+use crate::severity::Severity;
+use crate::span::Span;
+use crate::utils::debug_log;
+use crate::worker::PostHogWorker;
+use crate::{TelemetryConfig, TelemetryKeys, TelemetryProps, TelemetryResult};
 use once_cell::sync::OnceCell;
-use posthog_rs::{
-    client, Client as PostHogClient, ClientOptionsBuilder as PostHogClientOptionsBuilder, Event,
-    EventBase, Exception,
-};
 use sentry;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+/// Telemetry client sending events to PostHog and errors to Sentry or PostHog.
+///
+/// Events are delivered by a dedicated background thread, so every method is
+/// available both as `async` and as a `_blocking` variant for synchronous apps,
+/// and the async methods work on any executor.
 pub struct Telemetry {
-    app_name: String,
-    app_version: String,
-    config: TelemetryConfig,
-    posthog: Option<PostHogClient>,
-    sentry_guard: Option<sentry::ClientInitGuard>,
+    pub(crate) context: Arc<TelemetryContext>,
+    pub(crate) config: TelemetryConfig,
+    pub(crate) posthog: Option<PostHogWorker>,
+    pub(crate) sentry_guard: Option<sentry::ClientInitGuard>,
+    pub(crate) sampling: SamplingConfig,
+    pub(crate) rate_limiter: RateLimiter,
+    pub(crate) session_events: bool,
+    pub(crate) breadcrumbs: Arc<Breadcrumbs>,
+    pub(crate) scrub: Arc<ScrubConfig>,
+    /// Id of the panic reporter set by this instance, cleared when it's dropped
+    pub(crate) panic_reporter: Option<u64>,
 }
 
 impl Telemetry {
+    /// Creates a telemetry instance. The keys are validated even if they were
+    /// constructed by hand, so invalid keys never reach the PostHog or Sentry clients.
+    ///
+    /// Any failure is reported as [`TelemetryError::InitializationError`](crate::TelemetryError::InitializationError).
     pub async fn new(
         app_name: &str,
         app_version: &str,
         config_name: &str,
-        posthog_key: Option<String>,
-        sentry_dsn: Option<String>,
+        keys: TelemetryKeys,
         custom_config_path: Option<std::path::PathBuf>,
     ) -> TelemetryResult<Self> {
-        let config = TelemetryConfig::new(config_name, custom_config_path)?;
-
-        let (posthog, sentry_guard) = if config.enabled {
-            let posthog = if let Some(key) = posthog_key {
-                let app = app_name.to_string();
-                let version = app_version.to_string();
-                let client_options = PostHogClientOptionsBuilder::default()
-                    .api_key(key)
-                    .default_distinct_id(config.instance_id.clone())
-                    .enable_panic_capturing(sentry_dsn.is_none())
-                    .on_panic_exception(Some(Arc::new(move |panic_exception: &mut Exception| {
-                        let _ =
-                            Telemetry::add_posthog_default_props(panic_exception, &app, &version);
-                    })))
-                    .build()
-                    .expect("Failed to build posthog client options");
-                Some(client(client_options).await)
-            } else {
-                None
-            };
-
-            let sentry_guard = if let Some(dsn) = sentry_dsn {
-                let options = sentry::ClientOptions {
-                    release: Some(env!("CARGO_PKG_VERSION").into()),
-                    ..Default::default()
-                };
-
-                // Initialize Sentry and store the guard
-                let guard = sentry::init((dsn, options));
-
-                // Configure scope with default tags
-                sentry::configure_scope(|scope| {
-                    scope.set_tag("app", app_name);
-                    scope.set_tag("app_version", app_version);
-                    scope.set_tag("platform", std::env::consts::OS);
-                    scope.set_tag("zksync_telemetry_version", env!("CARGO_PKG_VERSION"));
-                });
+        Self::new_blocking(app_name, app_version, config_name, keys, custom_config_path)
+    }
 
-                Some(guard)
-            } else {
-                None
-            };
+    /// Blocking version of [`Telemetry::new`]
+    pub fn new_blocking(
+        app_name: &str,
+        app_version: &str,
+        config_name: &str,
+        keys: TelemetryKeys,
+        custom_config_path: Option<std::path::PathBuf>,
+    ) -> TelemetryResult<Self> {
+        Self::builder(app_name, app_version, config_name)
+            .keys(keys)
+            .custom_config_path(custom_config_path)
+            .build()
+    }
 
-            (posthog, sentry_guard)
-        } else {
-            (None, None)
-        };
+    /// Creates a builder for more advanced configuration
+    pub fn builder(app_name: &str, app_version: &str, config_name: &str) -> TelemetryBuilder {
+        TelemetryBuilder::new(app_name, app_version, config_name)
+    }
 
-        Ok(Self {
-            app_name: app_name.to_string(),
-            app_version: app_version.to_string(),
-            config,
-            posthog,
-            sentry_guard,
-        })
+    /// Same as [`Telemetry::new`], but never fails. If initialization fails,
+    /// the error is logged and a disabled instance is returned instead.
+    pub async fn new_best_effort(
+        app_name: &str,
+        app_version: &str,
+        config_name: &str,
+        keys: TelemetryKeys,
+        custom_config_path: Option<std::path::PathBuf>,
+    ) -> Self {
+        Self::builder(app_name, app_version, config_name)
+            .keys(keys)
+            .custom_config_path(custom_config_path)
+            .build_best_effort()
+    }
+
+    /// Creates an instance that does not collect anything
+    pub fn disabled(app_name: &str, app_version: &str) -> Self {
+        Self {
+            context: Arc::new(TelemetryContext::new(
+                app_name,
+                app_version,
+                None,
+                &PlatformProperties::none(),
+            )),
+            config: TelemetryConfig::disabled(),
+            posthog: None,
+            sentry_guard: None,
+            sampling: SamplingConfig::default(),
+            rate_limiter: RateLimiter::new(RateLimitConfig::unlimited()),
+            session_events: false,
+            breadcrumbs: Default::default(),
+            scrub: Arc::new(ScrubConfig::none()),
+            panic_reporter: None,
+        }
     }
 
     pub async fn track_event(
@@ -86,120 +114,459 @@ impl Telemetry {
         event_name: &str,
         properties: TelemetryProps,
     ) -> TelemetryResult<()> {
-        if !self.config.enabled {
-            return Ok(());
+        if let Some((posthog, event)) = self.prepare_event(event_name, properties) {
+            posthog.capture(event).await?;
         }
 
-        if let Some(client) = &self.posthog {
-            let mut event = Event::new(event_name, &self.config.instance_id);
+        Ok(())
+    }
 
-            if let Some(props_map) = properties.to_map() {
-                for (key, value) in props_map {
-                    event
-                        .insert_prop(key, value)
-                        .map_err(|e| TelemetryError::SendError(e.to_string()))?;
-                }
-            }
-            Telemetry::add_posthog_default_props(&mut event, &self.app_name, &self.app_version)?;
+    /// Blocking version of [`Telemetry::track_event`].
+    /// Must not be called from within an async runtime.
+    pub fn track_event_blocking(
+        &self,
+        event_name: &str,
+        properties: TelemetryProps,
+    ) -> TelemetryResult<()> {
+        if let Some((posthog, event)) = self.prepare_event(event_name, properties) {
+            posthog.capture_blocking(event)?;
+        }
 
-            client
-                .capture(event)
-                .await
-                .map_err(|e| TelemetryError::SendError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Reports the error with its cause chain, and its backtrace if available,
+    /// see [`ErrorReport`]
+    pub async fn track_error(&self, error: impl Into<ErrorReport>) -> TelemetryResult<()> {
+        if let Some((posthog, exception)) = self.prepare_error(error.into()) {
+            posthog.capture(exception).await?;
         }
 
         Ok(())
     }
 
-    pub async fn track_error(
+    /// Reports the error like [`Telemetry::track_error`], with properties describing
+    /// its circumstances, e.g. the command or stage that failed.
+    /// For the blocking and detached versions, see [`ErrorReport::with_properties`].
+    ///
+    /// ```no_run
+    /// # async fn run(telemetry: &zksync_telemetry::Telemetry, error: &std::io::Error) -> zksync_telemetry::TelemetryResult<()> {
+    /// use zksync_telemetry::TelemetryProps;
+    ///
+    /// let properties = TelemetryProps::new()
+    ///     .insert("command", Some("deploy"))
+    ///     .insert("stage", Some("verification"))
+    ///     .take();
+    /// telemetry.track_error_with(error, properties).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn track_error_with(
         &self,
-        error: Box<&(dyn std::error::Error + Send + Sync)>,
+        error: impl Into<ErrorReport>,
+        properties: TelemetryProps,
     ) -> TelemetryResult<()> {
+        self.track_error(error.into().with_properties(properties))
+            .await
+    }
+
+    /// Blocking version of [`Telemetry::track_error`].
+    /// Must not be called from within an async runtime.
+    pub fn track_error_blocking(&self, error: impl Into<ErrorReport>) -> TelemetryResult<()> {
+        if let Some((posthog, exception)) = self.prepare_error(error.into()) {
+            posthog.capture_blocking(exception)?;
+        }
+
+        Ok(())
+    }
+
+    /// Queues the event and returns immediately, without waiting for it to be sent.
+    /// Delivery failures are only visible through [`Telemetry::diagnostics`].
+    pub fn track_event_detached(&self, event_name: &str, properties: TelemetryProps) {
+        if let Some((posthog, event)) = self.prepare_event(event_name, properties) {
+            posthog.capture_detached(event);
+        }
+    }
+
+    /// Queues the error report and returns immediately, without waiting for it to be sent.
+    /// Delivery failures are only visible through [`Telemetry::diagnostics`].
+    pub fn track_error_detached(&self, error: impl Into<ErrorReport>) {
+        if let Some((posthog, exception)) = self.prepare_error(error.into()) {
+            posthog.capture_detached(exception);
+        }
+    }
+
+    /// Reports an anomaly that isn't an error, e.g. "fallback RPC used", and returns
+    /// immediately like [`Telemetry::track_error_detached`]. It's reported like errors,
+    /// with the same context, rate limiting and breadcrumbs: as a message to Sentry,
+    /// and as a `$exception` event with a synthetic `Message` exception to PostHog.
+    pub fn capture_message(&self, message: &str, severity: Severity, properties: TelemetryProps) {
+        let report = ErrorReport::from_message(message)
+            .with_severity(severity)
+            .with_properties(properties);
+        if let Some((posthog, exception)) = self.prepare_error(report) {
+            posthog.capture_detached(exception);
+        }
+    }
+
+    /// Blocks until all queued events are sent or `timeout` elapses, after queuing
+    /// the occurrence counts of deduplicated errors, see [`RateLimitConfig`].
+    /// Returns `false` on timeout. Call it before exiting to avoid losing detached events.
+    pub fn flush(&self, timeout: Duration) -> bool {
+        self.report_suppressed_errors(false);
+        // `None` if the timeout is too large to represent, e.g. `Duration::MAX`
+        let deadline = Instant::now().checked_add(timeout);
+        let posthog_flushed = self
+            .posthog
+            .as_ref()
+            .is_none_or(|posthog| posthog.flush(timeout));
+        let sentry_flushed = self.sentry_guard.is_none()
+            || sentry::Hub::current().client().is_none_or(|client| {
+                let remaining = deadline.map_or(timeout, |deadline| {
+                    deadline.saturating_duration_since(Instant::now())
+                });
+                client.flush(Some(remaining))
+            });
+        posthog_flushed && sentry_flushed
+    }
+
+    /// Returns the PostHog delivery counters. Errors sent to Sentry are not included.
+    pub fn diagnostics(&self) -> TelemetryDiagnostics {
+        self.posthog
+            .as_ref()
+            .map(PostHogWorker::diagnostics)
+            .unwrap_or_default()
+    }
+
+    /// Registers a property attached to every subsequent event and error report,
+    /// replacing any previous value for `key`.
+    /// Event properties take precedence over it, and it's sent as a tag to Sentry.
+    pub fn register_property(&self, key: impl ToString, value: impl Into<TelemetryProps>) {
+        self.context
+            .register_property(key.to_string(), value.into().to_inner());
+    }
+
+    /// Registers every property of `properties`, see [`Telemetry::register_property`]
+    pub fn register_properties(&self, properties: TelemetryProps) {
+        if let Some(props_map) = properties.to_map() {
+            self.context.register_properties(props_map);
+        }
+    }
+
+    /// Stops attaching the property registered under `key`
+    pub fn unregister_property(&self, key: &str) {
+        self.context.unregister_property(key);
+    }
+
+    /// Records a breadcrumb attached to subsequent error reports, natively to Sentry
+    /// and as the `breadcrumbs` property to PostHog. Only the most recent ones are kept.
+    /// Values of keys that look sensitive, e.g. `private_key`, are redacted.
+    pub fn add_breadcrumb(&self, category: &str, message: &str, data: TelemetryProps) {
+        self.record_breadcrumb(
+            Breadcrumb::new(category, message, Severity::Info)
+                .with_data(data.to_map().unwrap_or_default()),
+        );
+    }
+
+    /// Starts timing an operation. When the returned span is finished or dropped,
+    /// an event named `name` is queued with its duration and outcome.
+    ///
+    /// ```no_run
+    /// # fn deploy() -> std::io::Result<()> { Ok(()) }
+    /// # fn run(telemetry: &zksync_telemetry::Telemetry) {
+    /// let mut span = telemetry.start_span("deploy");
+    /// span.insert("network", "sepolia");
+    /// let result = deploy();
+    /// span.finish_result(&result);
+    /// # }
+    /// ```
+    pub fn start_span(&self, name: &str) -> Span<'_> {
+        Span::new(self, name)
+    }
+
+    /// Returns the id of the current session, attached to every event and error report
+    /// as `session_id`. By default all instances in a process share one session.
+    pub fn session_id(&self) -> String {
+        self.context.session_id()
+    }
+
+    /// Ends the current session with [`SessionStatus::Unknown`] if it's still active,
+    /// and starts a new one with the given id, or a random one
+    pub fn start_session(&self, session_id: Option<&str>) {
+        self.finish_session(SessionStatus::Unknown);
+        let session_id = session_id
+            .map(str::to_string)
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        self.context.start_session(session_id);
+        self.session_started();
+    }
+
+    /// Ends the current session. If session events are enabled, `session_ended` is
+    /// queued with the session duration and `status`; call [`Telemetry::flush`] to deliver it.
+    pub fn end_session(&self, status: SessionStatus) {
+        self.finish_session(status);
+    }
+
+    /// Ends the current session with `status` and waits up to 2 seconds for queued
+    /// events to be sent, see [`Telemetry::flush`]. Call it before the app exits:
+    /// unlike dropping the instance, it reports how the session ended.
+    /// Returns `false` on timeout.
+    pub fn shutdown(&self, status: SessionStatus) -> bool {
+        self.finish_session(status);
+        self.flush(SHUTDOWN_TIMEOUT)
+    }
+
+    pub(crate) fn session_started(&self) {
+        if !self.session_events {
+            return;
+        }
+        if let Some((posthog, event)) = self.build_event("session_started", TelemetryProps::new()) {
+            posthog.capture_detached(event);
+        }
+    }
+
+    /// Returns whether a `session_ended` event was queued
+    fn finish_session(&self, status: SessionStatus) -> bool {
+        let Some(mut event) = self.context.end_session(status, &self.config.instance_id) else {
+            return false;
+        };
+        match &self.posthog {
+            Some(posthog) if self.session_events && self.config.enabled => {
+                self.scrub.scrub_map(event.properties_mut());
+                posthog.capture_detached(event);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Builds the PostHog event, or returns `None` if there is nothing to send
+    /// or the event was sampled out or rate limited
+    fn prepare_event(
+        &self,
+        event_name: &str,
+        properties: TelemetryProps,
+    ) -> Option<(&PostHogWorker, PostHogEvent)> {
         if !self.config.enabled {
-            return Ok(());
+            return None;
         }
+        self.record_breadcrumb(
+            Breadcrumb::new("event", event_name, Severity::Info)
+                .with_data(properties.clone().to_map().unwrap_or_default()),
+        );
+        self.posthog.as_ref()?;
+        self.report_suppressed_errors(true);
 
-        if self.sentry_guard.is_some() {
-            sentry::capture_error(*error);
-        } else if let Some(posthog_client) = &self.posthog {
-            let mut exception = Exception::new(*error, &self.config.instance_id);
-            Telemetry::add_posthog_default_props(
-                &mut exception,
-                &self.app_name,
-                &self.app_version,
-            )?;
-
-            posthog_client
-                .capture_exception(exception)
-                .await
-                .map_err(|e| TelemetryError::SendError(e.to_string()))?;
+        let sample_rate = self.sampling.sample(event_name)?;
+        if !self.rate_limiter.allow_event() {
+            debug_log(&format!("Event {} dropped by rate limiting", event_name));
+            return None;
         }
 
-        Ok(())
+        let (posthog, mut event) = self.build_event(event_name, properties)?;
+        event.insert_prop("sample_rate", sample_rate);
+
+        Some((posthog, event))
     }
 
-    fn add_posthog_default_props(
-        event: &mut impl EventBase,
-        app_name: &str,
-        app_version: &str,
-    ) -> TelemetryResult<()> {
-        event
-            .insert_prop("app", app_name)
-            .map_err(|e| TelemetryError::SendError(e.to_string()))?;
-        event
-            .insert_prop("app_version", app_version)
-            .map_err(|e| TelemetryError::SendError(e.to_string()))?;
-        event
-            .insert_prop("platform", std::env::consts::OS)
-            .map_err(|e| TelemetryError::SendError(e.to_string()))?;
-        event
-            .insert_prop("zksync_telemetry_version", env!("CARGO_PKG_VERSION"))
-            .map_err(|e| TelemetryError::SendError(e.to_string()))?;
+    /// Builds the PostHog event with the default context, bypassing sampling and rate limiting
+    fn build_event(
+        &self,
+        event_name: &str,
+        properties: TelemetryProps,
+    ) -> Option<(&PostHogWorker, PostHogEvent)> {
+        if !self.config.enabled {
+            return None;
+        }
 
-        Ok(())
+        let posthog = self.posthog.as_ref()?;
+        let mut event = PostHogEvent::new(event_name, &self.config.instance_id);
+        self.context
+            .apply_to_event(&mut event, properties.to_map().unwrap_or_default());
+        self.scrub.scrub_map(event.properties_mut());
+
+        Some((posthog, event))
+    }
+
+    /// Reports the error to Sentry if it's configured, otherwise builds a PostHog
+    /// exception and returns it for sending. Repeated errors are deduplicated.
+    fn prepare_error(&self, report: ErrorReport) -> Option<(&PostHogWorker, PostHogEvent)> {
+        if !self.reports_errors() {
+            return None;
+        }
+        self.report_suppressed_errors(true);
+
+        let occurrences = self.rate_limiter.check_error(&report)?;
+        self.build_error(&report, occurrences)
+    }
+
+    /// Resends deduplicated errors that occurred again since their last report with
+    /// the number of these occurrences, only once their window expired if `expired_only`.
+    /// Returns whether any report was sent or queued.
+    fn report_suppressed_errors(&self, expired_only: bool) -> bool {
+        if !self.reports_errors() {
+            return false;
+        }
+        let suppressed = self.rate_limiter.take_suppressed_errors(expired_only);
+        for (report, occurrences) in &suppressed {
+            if let Some((posthog, exception)) = self.build_error(report, *occurrences) {
+                posthog.capture_detached(exception);
+            }
+        }
+        !suppressed.is_empty()
+    }
+
+    fn reports_errors(&self) -> bool {
+        self.config.enabled && (self.sentry_guard.is_some() || self.posthog.is_some())
+    }
+
+    /// Same as [`Telemetry::prepare_error`], bypassing rate limiting
+    fn build_error(
+        &self,
+        report: &ErrorReport,
+        occurrences: u64,
+    ) -> Option<(&PostHogWorker, PostHogEvent)> {
+        let breadcrumbs = self.breadcrumbs.snapshot();
+
+        if self.sentry_guard.is_some() {
+            sentry::with_scope(
+                |scope| {
+                    self.context
+                        .apply_to_scope(scope, report.properties().clone());
+                    scope.set_extra("occurrences", occurrences.into());
+                    breadcrumbs::apply_to_scope(&breadcrumbs, scope);
+                },
+                || sentry::capture_event(report.to_sentry_event()),
+            );
+        } else if let Some(posthog) = &self.posthog {
+            let mut exception = PostHogEvent::exception(report, &self.config.instance_id);
+            self.context
+                .apply_to_event(&mut exception, report.properties().clone());
+            exception.insert_prop("occurrences", occurrences);
+            breadcrumbs::apply_to_event(breadcrumbs, &mut exception);
+            self.scrub.scrub_map(exception.properties_mut());
+
+            return Some((posthog, exception));
+        }
+
+        None
     }
 
-    // No need for explicit shutdown now as the guard handles it
+    /// Records a breadcrumb attached to subsequent error reports
+    pub(crate) fn record_breadcrumb(&self, breadcrumb: Breadcrumb) {
+        if self.config.enabled {
+            self.breadcrumbs.add(breadcrumb);
+        }
+    }
 }
 
+impl Drop for Telemetry {
+    /// Ends the session as crashed if the instance is dropped while unwinding from
+    /// a panic, which hasn't been caught in the frames that owned it
+    fn drop(&mut self) {
+        if let Some(id) = self.panic_reporter {
+            panic::clear_reporter(id);
+        }
+        let status = if std::thread::panicking() {
+            SessionStatus::Crashed
+        } else {
+            SessionStatus::Unknown
+        };
+        let session_ended = self.finish_session(status);
+        if self.report_suppressed_errors(false) || session_ended {
+            self.flush(SHUTDOWN_TIMEOUT);
+        }
+    }
+}
+
+/// How long shutting down or dropping the instance waits for `session_ended`
+/// and the occurrence counts of deduplicated errors to be sent
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
 static TELEMETRY: OnceCell<Telemetry> = OnceCell::new();
 
+/// Sets `cell` to the instance returned by `build`. If it's already set, `build`
+/// isn't called: building an instance rebinds Sentry and the panic hook, and dropping
+/// it would close the Sentry client of the instance already set.
+fn init_once(
+    cell: &OnceCell<Telemetry>,
+    build: impl FnOnce() -> TelemetryResult<Telemetry>,
+) -> anyhow::Result<()> {
+    let mut built = false;
+    cell.get_or_try_init(|| {
+        built = true;
+        build()
+    })?;
+    if !built {
+        anyhow::bail!("Telemetry is already set");
+    }
+    Ok(())
+}
+
 pub async fn init_telemetry(
     app_name: &str,
     app_version: &str,
     config_name: &str,
-    posthog_key: Option<String>,
-    sentry_dsn: Option<String>,
+    keys: TelemetryKeys,
     custom_config_path: Option<std::path::PathBuf>,
 ) -> anyhow::Result<()> {
-    let telemetry = Telemetry::new(
-        app_name,
-        app_version,
-        config_name,
-        posthog_key,
-        sentry_dsn,
-        custom_config_path,
-    )
-    .await?;
-    TELEMETRY
-        .set(telemetry)
-        .map_err(|_| anyhow::format_err!("Telemetry is already set"))
+    init_telemetry_blocking(app_name, app_version, config_name, keys, custom_config_path)
+}
+
+/// Blocking version of [`init_telemetry`]
+pub fn init_telemetry_blocking(
+    app_name: &str,
+    app_version: &str,
+    config_name: &str,
+    keys: TelemetryKeys,
+    custom_config_path: Option<std::path::PathBuf>,
+) -> anyhow::Result<()> {
+    init_once(&TELEMETRY, || {
+        Telemetry::new_blocking(app_name, app_version, config_name, keys, custom_config_path)
+    })
+}
+
+/// Same as [`init_telemetry`], but never fails. Falls back to a disabled
+/// instance if initialization fails, and keeps the existing instance if one is already set.
+pub async fn init_telemetry_best_effort(
+    app_name: &str,
+    app_version: &str,
+    config_name: &str,
+    keys: TelemetryKeys,
+    custom_config_path: Option<std::path::PathBuf>,
+) {
+    let result = init_once(&TELEMETRY, || {
+        Ok(Telemetry::builder(app_name, app_version, config_name)
+            .keys(keys)
+            .custom_config_path(custom_config_path)
+            .build_best_effort())
+    });
+    if let Err(e) = result {
+        debug_log(&e.to_string());
+    }
 }
 
 pub fn get_telemetry() -> Option<&'static Telemetry> {
     TELEMETRY.get()
 }
 
+/// Shuts down the global instance, see [`Telemetry::shutdown`]. Statics are never
+/// dropped, so without it the session of the global instance is never reported as ended.
+/// Returns `false` on timeout, and `true` if there is no global instance.
+pub fn shutdown_telemetry(status: SessionStatus) -> bool {
+    TELEMETRY
+        .get()
+        .is_none_or(|telemetry| telemetry.shutdown(status))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tempfile::TempDir;
-
-    fn setup() -> (TempDir, String) {
-        let temp_dir = TempDir::new().unwrap();
-        let config_path = temp_dir.path().join("telemetry.json");
-        (temp_dir, config_path.to_str().unwrap().to_string())
-    }
+    use crate::test_utils::{
+        enable_telemetry, enabled_telemetry, keys, posthog_telemetry, sent_requests, setup,
+        MockTransport,
+    };
+    use crate::{PanicReporting, SpanOutcome, TelemetryError};
 
     #[tokio::test]
     async fn test_telemetry_disabled_by_default_in_tests() {
@@ -209,8 +576,7 @@ mod tests {
             "test-app",
             "1.0.0",
             "zksync-telemetry",
-            Some("fake-key".to_string()),
-            Some("fake-dsn".to_string()),
+            keys(Some("phc_fake_key"), Some("https://public@sentry.io/1")),
             Some(config_path.into()),
         )
         .await
@@ -219,6 +585,68 @@ mod tests {
         assert!(!telemetry.config.enabled);
     }
 
+    #[tokio::test]
+    async fn test_invalid_keys_are_rejected() {
+        let (_, config_path) = setup();
+
+        let mut invalid_keys = keys(None, None);
+        invalid_keys.posthog_key = Some("fake-key".into());
+
+        let telemetry = Telemetry::new(
+            "test-app",
+            "1.0.0",
+            "zksync-telemetry",
+            invalid_keys,
+            Some(config_path.into()),
+        )
+        .await;
+
+        assert!(matches!(
+            telemetry,
+            Err(TelemetryError::InitializationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_ingest_dsn_is_accepted() {
+        let (_, config_path) = setup();
+
+        let telemetry = Telemetry::builder("test-app", "1.0.0", "zksync-telemetry")
+            .keys(
+                TelemetryKeys::with_keys(
+                    None,
+                    Some("https://abc@o4505.ingest.us.sentry.io/123".to_string()),
+                )
+                .unwrap(),
+            )
+            .custom_config_path(Some(config_path.into()))
+            .build();
+
+        assert!(telemetry.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_best_effort_falls_back_to_disabled() {
+        let (temp_dir, _) = setup();
+
+        // A directory is not a valid config file
+        let telemetry = Telemetry::new_best_effort(
+            "test-app",
+            "1.0.0",
+            "zksync-telemetry",
+            keys(Some("phc_fake_key"), None),
+            Some(temp_dir.path().to_path_buf()),
+        )
+        .await;
+
+        assert!(!telemetry.config.enabled);
+        assert!(telemetry.posthog.is_none());
+        assert!(telemetry
+            .track_event("test_event", TelemetryProps::new())
+            .await
+            .is_ok());
+    }
+
     #[tokio::test]
     async fn test_track_event_when_disabled() {
         let (_, config_path) = setup();
@@ -227,8 +655,7 @@ mod tests {
             "test-app",
             "1.0.0",
             "zksync-telemetry",
-            None,
-            None,
+            keys(None, None),
             Some(config_path.into()),
         )
         .await
@@ -250,18 +677,14 @@ mod tests {
             "test-app",
             "1.0.0",
             "zksync-telemetry",
-            None,
-            Some("https://public@example.com/1".to_string()),
+            keys(None, Some("https://public@sentry.io/1")),
             Some(config_path.into()),
         )
         .await
         .unwrap();
 
         assert!(telemetry
-            .track_error(Box::new(&std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "test error"
-            )))
+            .track_error(&std::io::Error::other("test error"))
             .await
             .is_ok());
     }
@@ -274,22 +697,510 @@ mod tests {
             "test-app",
             "1.0.0",
             "zksync-telemetry",
-            Some("fake-key".to_string()),
-            None,
+            keys(Some("phc_fake_key"), None),
             Some(config_path.into()),
         )
         .await
         .unwrap();
 
         assert!(telemetry
-            .track_error(Box::new(&std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "test error"
-            )))
+            .track_error(&std::io::Error::other("test error"))
             .await
             .is_ok());
     }
 
+    #[test]
+    fn test_blocking_api() {
+        let (_, config_path) = setup();
+
+        let telemetry = Telemetry::new_blocking(
+            "test-app",
+            "1.0.0",
+            "zksync-telemetry",
+            keys(Some("phc_fake_key"), None),
+            Some(config_path.into()),
+        )
+        .unwrap();
+
+        let properties = TelemetryProps::new().insert("test", Some("value")).take();
+        assert!(telemetry
+            .track_event_blocking("test_event", properties)
+            .is_ok());
+        assert!(telemetry
+            .track_error_blocking(&std::io::Error::other("test error"))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_async_api_without_tokio() {
+        let (_temp_dir, config_path) = setup();
+        let config = enable_telemetry(&config_path);
+        let transport = MockTransport::new(200);
+        let telemetry = posthog_telemetry(&config_path, transport.clone());
+
+        smol::block_on(async {
+            let properties = TelemetryProps::new().insert("test", Some("value")).take();
+            telemetry
+                .track_event("test_event", properties)
+                .await
+                .unwrap();
+            telemetry
+                .track_error(&std::io::Error::other("test error"))
+                .await
+                .unwrap();
+        });
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0]["api_key"], "phc_fake_key");
+        assert_eq!(requests[0]["event"], "test_event");
+        assert_eq!(requests[0]["distinct_id"], config.instance_id.as_str());
+        assert_eq!(requests[0]["properties"]["test"], "value");
+        assert_eq!(requests[0]["properties"]["app"], "test-app");
+        assert_eq!(requests[1]["event"], "$exception");
+        assert_eq!(
+            requests[1]["properties"]["$exception_list"][0]["value"],
+            "test error"
+        );
+    }
+
+    #[test]
+    fn test_detached_tracking() {
+        let (_temp_dir, config_path) = setup();
+        enable_telemetry(&config_path);
+        let transport = MockTransport::new(200);
+        let telemetry = posthog_telemetry(&config_path, transport.clone());
+
+        telemetry.track_event_detached("test_event", TelemetryProps::new());
+        telemetry.track_error_detached(&std::io::Error::other("test error"));
+        assert!(telemetry.flush(Duration::MAX));
+
+        assert_eq!(transport.requests.lock().unwrap().len(), 2);
+        let diagnostics = telemetry.diagnostics();
+        assert_eq!(diagnostics.events_enqueued, 2);
+        assert_eq!(diagnostics.events_sent, 2);
+        assert_eq!(diagnostics.send_failures, 0);
+    }
+
+    #[test]
+    fn test_detached_failures_are_counted() {
+        let (_temp_dir, config_path) = setup();
+        enable_telemetry(&config_path);
+        let telemetry = posthog_telemetry(&config_path, MockTransport::new(500));
+
+        telemetry.track_event_detached("test_event", TelemetryProps::new());
+        assert!(telemetry.flush(Duration::from_secs(5)));
+
+        let diagnostics = telemetry.diagnostics();
+        assert_eq!(diagnostics.events_sent, 0);
+        assert_eq!(diagnostics.send_failures, 1);
+        assert!(diagnostics.last_error.unwrap().contains("500"));
+    }
+
+    #[test]
+    fn test_sampling() {
+        let (_temp_dir, config_path) = setup();
+        enable_telemetry(&config_path);
+        let transport = MockTransport::new(200);
+        let telemetry = Telemetry::builder("test-app", "1.0.0", "zksync-telemetry")
+            .keys(keys(Some("phc_fake_key"), None))
+            .custom_config_path(Some(config_path.into()))
+            .transport(transport.clone())
+            .sampling(SamplingConfig::new().event_rate("rpc_call", 0.0))
+            .panic_reporting(PanicReporting::Disabled)
+            .build()
+            .unwrap();
+
+        telemetry
+            .track_event_blocking("rpc_call", TelemetryProps::new())
+            .unwrap();
+        telemetry
+            .track_event_blocking("command_executed", TelemetryProps::new())
+            .unwrap();
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0]["event"], "command_executed");
+        assert_eq!(requests[0]["properties"]["sample_rate"], 1.0);
+    }
+
+    #[test]
+    fn test_repeated_errors_are_deduplicated() {
+        let (_temp_dir, config_path) = setup();
+        enable_telemetry(&config_path);
+        let transport = MockTransport::new(200);
+        let telemetry = posthog_telemetry(&config_path, transport.clone());
+
+        for _ in 0..100 {
+            telemetry.track_error_detached(&std::io::Error::other("test error"));
+        }
+        telemetry.track_error_detached(&std::io::Error::other("another error"));
+        assert!(telemetry.flush(Duration::from_secs(5)));
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0]["properties"]["occurrences"], 1);
+        assert_eq!(
+            requests[1]["properties"]["$exception_list"][0]["value"],
+            "another error"
+        );
+        // The repetitions are reported on flush
+        let exception = &requests[2]["properties"];
+        assert_eq!(exception["$exception_list"][0]["value"], "test error");
+        assert_eq!(exception["occurrences"], 99);
+    }
+
+    #[test]
+    fn test_repeated_errors_are_reported_on_drop() {
+        let (_temp_dir, config_path) = setup();
+        enable_telemetry(&config_path);
+        let transport = MockTransport::new(200);
+        let telemetry = posthog_telemetry(&config_path, transport.clone());
+
+        for _ in 0..3 {
+            telemetry.track_error_detached(&std::io::Error::other("test error"));
+        }
+        drop(telemetry);
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1]["properties"]["occurrences"], 2);
+    }
+
+    #[test]
+    fn test_error_chain() {
+        let (_temp_dir, config_path) = setup();
+        enable_telemetry(&config_path);
+        let transport = MockTransport::new(200);
+        let telemetry = posthog_telemetry(&config_path, transport.clone());
+
+        let error = anyhow::Error::new(std::io::Error::other("disk full")).context("deploying");
+        telemetry.track_error_detached(error);
+        assert!(telemetry.flush(Duration::from_secs(5)));
+
+        let requests = transport.requests.lock().unwrap();
+        let exceptions = &requests[0]["properties"]["$exception_list"];
+        assert_eq!(exceptions[0]["value"], "deploying");
+        assert_eq!(exceptions[0]["mechanism"]["handled"], true);
+        assert_eq!(exceptions[1]["value"], "disk full");
+    }
+
+    #[tokio::test]
+    async fn test_error_properties() {
+        let (_temp_dir, config_path) = setup();
+        enable_telemetry(&config_path);
+        let transport = MockTransport::new(200);
+        let telemetry = posthog_telemetry(&config_path, transport.clone());
+
+        let error: &(dyn std::error::Error + Send + Sync + 'static) =
+            &std::io::Error::other("test error");
+        let properties = TelemetryProps::new()
+            .insert("command", Some("deploy"))
+            .take();
+        assert!(telemetry.track_error_with(error, properties).await.is_ok());
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests[0]["properties"]["command"], "deploy");
+        assert_eq!(
+            requests[0]["properties"]["$exception_list"][0]["value"],
+            "test error"
+        );
+    }
+
+    #[test]
+    fn test_error_severity() {
+        let (_temp_dir, config_path) = setup();
+        enable_telemetry(&config_path);
+        let transport = MockTransport::new(200);
+        let telemetry = posthog_telemetry(&config_path, transport.clone());
+
+        let error = std::io::Error::other("fallback RPC unreachable");
+        telemetry.track_error_detached(&error);
+        telemetry.track_error_detached(
+            ErrorReport::new(&std::io::Error::other("config corrupted"))
+                .with_severity(Severity::Fatal)
+                .with_handled(false),
+        );
+        assert!(telemetry.flush(Duration::from_secs(5)));
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests[0]["properties"]["$exception_level"], "error");
+        assert_eq!(requests[0]["properties"]["handled"], true);
+        assert_eq!(requests[1]["properties"]["$exception_level"], "fatal");
+        assert_eq!(requests[1]["properties"]["handled"], false);
+        assert_eq!(
+            requests[1]["properties"]["$exception_list"][0]["mechanism"]["handled"],
+            false
+        );
+    }
+
+    #[test]
+    fn test_capture_message() {
+        let (_temp_dir, config_path) = setup();
+        enable_telemetry(&config_path);
+        let transport = MockTransport::new(200);
+        let telemetry = posthog_telemetry(&config_path, transport.clone());
+        telemetry.register_property("network", "sepolia");
+
+        let properties = TelemetryProps::new().insert("rpc", Some("fallback")).take();
+        telemetry.capture_message("fallback RPC used", Severity::Warning, properties.clone());
+        telemetry.capture_message("fallback RPC used", Severity::Warning, properties);
+        assert!(telemetry.flush(Duration::from_secs(5)));
+
+        let requests = transport.requests.lock().unwrap();
+        // The repetition is deduplicated and reported on flush
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1]["properties"]["occurrences"], 1);
+        let properties = &requests[0]["properties"];
+        assert_eq!(requests[0]["event"], "$exception");
+        assert_eq!(properties["$exception_level"], "warning");
+        assert_eq!(properties["$exception_list"][0]["type"], "Message");
+        assert_eq!(
+            properties["$exception_list"][0]["value"],
+            "fallback RPC used"
+        );
+        assert_eq!(
+            properties["$exception_list"][0]["mechanism"]["synthetic"],
+            true
+        );
+        assert_eq!(properties["rpc"], "fallback");
+        assert_eq!(properties["network"], "sepolia");
+    }
+
+    #[test]
+    fn test_events_and_error_reports_are_scrubbed() {
+        let (_temp_dir, config_path) = setup();
+        enable_telemetry(&config_path);
+        let transport = MockTransport::new(200);
+        let telemetry = Telemetry::builder("test-app", "1.0.0", "zksync-telemetry")
+            .keys(keys(Some("phc_fake_key"), None))
+            .custom_config_path(Some(config_path.into()))
+            .transport(transport.clone())
+            .panic_reporting(PanicReporting::Disabled)
+            .scrub(ScrubConfig::none().pattern("/home/alice", "~"))
+            .build()
+            .unwrap();
+
+        let properties = TelemetryProps::new()
+            .insert("config", Some("/home/alice/app.toml"))
+            .take();
+        telemetry.track_event_detached("config_loaded", properties.clone());
+        let error = std::io::Error::other("failed to read /home/alice/app.toml");
+        telemetry.track_error_detached(ErrorReport::new(&error).with_properties(properties));
+        assert!(telemetry.flush(Duration::from_secs(5)));
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests[0]["properties"]["config"], "~/app.toml");
+        let properties = &requests[1]["properties"];
+        assert_eq!(
+            properties["$exception_list"][0]["value"],
+            "failed to read ~/app.toml"
+        );
+        assert_eq!(properties["config"], "~/app.toml");
+        assert_eq!(properties["breadcrumbs"][0]["data"]["config"], "~/app.toml");
+    }
+
+    #[test]
+    fn test_breadcrumbs() {
+        let (_temp_dir, telemetry, transport) = enabled_telemetry();
+
+        telemetry.track_event_detached(
+            "contract_deployed",
+            TelemetryProps::new()
+                .insert("network", Some("sepolia"))
+                .take(),
+        );
+        telemetry.add_breadcrumb(
+            "wallet",
+            "account imported",
+            TelemetryProps::new()
+                .insert("private_key", Some("0x1234"))
+                .take(),
+        );
+        telemetry.track_error_detached(&std::io::Error::other("test error"));
+
+        let requests = sent_requests(&telemetry, &transport);
+        assert!(requests[0]["properties"].get("breadcrumbs").is_none());
+        let breadcrumbs = &requests[1]["properties"]["breadcrumbs"];
+        assert_eq!(breadcrumbs.as_array().unwrap().len(), 2);
+        assert_eq!(breadcrumbs[0]["category"], "event");
+        assert_eq!(breadcrumbs[0]["message"], "contract_deployed");
+        assert_eq!(breadcrumbs[0]["data"]["network"], "sepolia");
+        assert_eq!(breadcrumbs[1]["category"], "wallet");
+        assert_eq!(breadcrumbs[1]["data"]["private_key"], "[REDACTED]");
+    }
+
+    #[test]
+    fn test_super_properties() {
+        let (_temp_dir, config_path) = setup();
+        enable_telemetry(&config_path);
+        let transport = MockTransport::new(200);
+        let telemetry = posthog_telemetry(&config_path, transport.clone());
+
+        telemetry.register_property("chain_id", "270");
+        telemetry.register_properties(
+            TelemetryProps::new()
+                .insert("network", Some("sepolia"))
+                .insert("l1_provider", Some("infura"))
+                .take(),
+        );
+        telemetry
+            .track_event_blocking(
+                "first",
+                TelemetryProps::new()
+                    .insert("network", Some("mainnet"))
+                    .take(),
+            )
+            .unwrap();
+        telemetry
+            .track_error_blocking(&std::io::Error::other("test error"))
+            .unwrap();
+
+        telemetry.register_property("chain_id", "300");
+        telemetry.unregister_property("l1_provider");
+        telemetry
+            .track_event_blocking("second", TelemetryProps::new())
+            .unwrap();
+
+        let requests = transport.requests.lock().unwrap();
+        let first = &requests[0]["properties"];
+        assert_eq!(first["chain_id"], "270");
+        assert_eq!(first["network"], "mainnet");
+        assert_eq!(first["l1_provider"], "infura");
+        let exception = &requests[1]["properties"];
+        assert_eq!(exception["chain_id"], "270");
+        assert_eq!(exception["network"], "sepolia");
+        let second = &requests[2]["properties"];
+        assert_eq!(second["chain_id"], "300");
+        assert!(second.get("l1_provider").is_none());
+    }
+
+    #[test]
+    fn test_sessions() {
+        let (_temp_dir, config_path) = setup();
+        enable_telemetry(&config_path);
+        let transport = MockTransport::new(200);
+        let telemetry = Telemetry::builder("test-app", "1.0.0", "zksync-telemetry")
+            .keys(keys(Some("phc_fake_key"), None))
+            .custom_config_path(Some(config_path.into()))
+            .transport(transport.clone())
+            .session_events(true)
+            .panic_reporting(PanicReporting::Disabled)
+            .build()
+            .unwrap();
+        let process_session = telemetry.session_id();
+
+        telemetry
+            .track_event_blocking("first", TelemetryProps::new())
+            .unwrap();
+        telemetry.start_session(Some("custom-session"));
+        telemetry
+            .track_error_blocking(&std::io::Error::other("test error"))
+            .unwrap();
+        telemetry.end_session(SessionStatus::Failure);
+        assert!(telemetry.flush(Duration::from_secs(5)));
+        // Already ended, nothing is sent when dropped
+        drop(telemetry);
+
+        let requests = transport.requests.lock().unwrap();
+        let events: Vec<_> = requests
+            .iter()
+            .map(|request| {
+                (
+                    request["event"].as_str().unwrap(),
+                    request["properties"]["session_id"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            events,
+            [
+                ("session_started", process_session.as_str()),
+                ("first", process_session.as_str()),
+                ("session_ended", process_session.as_str()),
+                ("session_started", "custom-session"),
+                ("$exception", "custom-session"),
+                ("session_ended", "custom-session"),
+            ]
+        );
+        assert_eq!(requests[2]["properties"]["exit_status"], "unknown");
+        assert_eq!(requests[5]["properties"]["exit_status"], "failure");
+        assert!(requests[5]["properties"]["duration_ms"].is_u64());
+    }
+
+    #[test]
+    fn test_spans() {
+        let (_temp_dir, config_path) = setup();
+        enable_telemetry(&config_path);
+        let transport = MockTransport::new(200);
+        let telemetry = posthog_telemetry(&config_path, transport.clone());
+
+        let mut span = telemetry.start_span("deploy");
+        span.insert("network", "sepolia");
+        span.finish(SpanOutcome::Success);
+
+        #[derive(Debug)]
+        struct BuildError;
+
+        impl std::fmt::Display for BuildError {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("build failed")
+            }
+        }
+
+        impl std::error::Error for BuildError {}
+
+        let result: Result<(), _> = Err(BuildError);
+        telemetry.start_span("build").finish_result(&result);
+        let result: anyhow::Result<()> = Err(BuildError.into());
+        if let Err(error) = &result {
+            telemetry
+                .start_span("build_anyhow")
+                .finish(SpanOutcome::from_error(&**error));
+        }
+
+        let early_return = || -> Option<()> {
+            let _span = telemetry.start_span("early_return");
+            None?;
+            Some(())
+        };
+        early_return();
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _span = telemetry.start_span("panicking");
+            panic!("test panic");
+        }));
+        assert!(result.is_err());
+
+        assert!(telemetry.flush(Duration::from_secs(5)));
+
+        let requests = transport.requests.lock().unwrap();
+        let events: Vec<_> = requests
+            .iter()
+            .map(|request| {
+                let properties = &request["properties"];
+                assert!(properties["duration_ms"].is_u64());
+                (
+                    request["event"].as_str().unwrap(),
+                    properties["success"].as_bool().unwrap(),
+                    properties["outcome"].as_str().unwrap(),
+                    properties["error_kind"].as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            events,
+            [
+                ("deploy", true, "success", None),
+                ("build", false, "failure", Some("BuildError")),
+                ("build_anyhow", false, "failure", Some("BuildError")),
+                ("early_return", false, "abandoned", None),
+                ("panicking", false, "panic", Some("panic")),
+            ]
+        );
+        assert_eq!(requests[0]["properties"]["network"], "sepolia");
+    }
+
     #[tokio::test]
     async fn test_telemetry_init() {
         let (_, config_path) = setup();
@@ -301,8 +1212,7 @@ mod tests {
             "test-app",
             "1.0.0",
             "zksync-telemetry",
-            Some("fake-key".to_string()),
-            Some("fake-dsn".to_string()),
+            keys(Some("phc_fake_key"), Some("https://public@sentry.io/1")),
             Some(config_path.into()),
         )
         .await
@@ -311,5 +1221,93 @@ mod tests {
         telemetry = get_telemetry();
 
         assert!(telemetry.is_some());
+        let result = init_telemetry(
+            "other-app",
+            "1.0.0",
+            "zksync-telemetry",
+            keys(None, None),
+            None,
+        )
+        .await;
+        assert!(result.is_err());
+        assert!(std::ptr::eq(get_telemetry().unwrap(), telemetry.unwrap()));
+        assert!(shutdown_telemetry(SessionStatus::Success));
+    }
+
+    #[test]
+    fn test_init_once() {
+        let (_temp_dir, config_path) = setup();
+        enable_telemetry(&config_path);
+        let transport = MockTransport::new(200);
+        let cell = OnceCell::new();
+
+        init_once(&cell, || {
+            Ok(posthog_telemetry(&config_path, transport.clone()))
+        })
+        .unwrap();
+        // A second instance is never built, so it can't replace or close anything
+        let result = init_once(&cell, || panic!("second instance built"));
+        assert!(result.is_err());
+
+        let telemetry = cell.get().unwrap();
+        telemetry.track_event_detached("test_event", TelemetryProps::new());
+        assert!(telemetry.flush(Duration::from_secs(5)));
+        assert_eq!(transport.requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_panics_end_sessions_only_when_uncaught() {
+        let (_temp_dir, config_path) = setup();
+        enable_telemetry(&config_path);
+        let transport = MockTransport::new(200);
+        let telemetry = Telemetry::builder("test-app", "1.0.0", "zksync-telemetry")
+            .keys(keys(Some("phc_fake_key"), None))
+            .custom_config_path(Some(config_path.into()))
+            .transport(transport.clone())
+            .session_events(true)
+            .panic_reporting(PanicReporting::Disabled)
+            .build()
+            .unwrap();
+
+        let result = std::panic::catch_unwind(|| panic!("caught panic"));
+        assert!(result.is_err());
+        telemetry
+            .track_event_blocking("after_panic", TelemetryProps::new())
+            .unwrap();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || {
+            let _telemetry = telemetry;
+            panic!("uncaught panic");
+        }));
+        assert!(result.is_err());
+
+        let requests = transport.requests.lock().unwrap();
+        let events: Vec<_> = requests
+            .iter()
+            .map(|request| request["event"].as_str().unwrap())
+            .collect();
+        assert_eq!(events, ["session_started", "after_panic", "session_ended"]);
+        assert_eq!(requests[2]["properties"]["exit_status"], "crashed");
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let (_temp_dir, config_path) = setup();
+        enable_telemetry(&config_path);
+        let transport = MockTransport::new(200);
+        let telemetry = Telemetry::builder("test-app", "1.0.0", "zksync-telemetry")
+            .keys(keys(Some("phc_fake_key"), None))
+            .custom_config_path(Some(config_path.into()))
+            .transport(transport.clone())
+            .session_events(true)
+            .panic_reporting(PanicReporting::Disabled)
+            .build()
+            .unwrap();
+
+        // Delivered without dropping the instance
+        assert!(telemetry.shutdown(SessionStatus::Success));
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1]["event"], "session_ended");
+        assert_eq!(requests[1]["properties"]["exit_status"], "success");
     }
 }
@@ -0,0 +1,86 @@
+//! Helpers shared by the unit tests.
+use crate::{
+    PanicReporting, RetryPolicy, Telemetry, TelemetryConfig, TelemetryKeys, TelemetryResult,
+    Transport, TransportRequest, TransportResponse,
+};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tempfile::TempDir;
+
+/// Records request bodies instead of sending them and responds with `status`
+#[derive(Clone)]
+pub(crate) struct MockTransport {
+    status: u16,
+    pub(crate) requests: Arc<Mutex<Vec<serde_json::Value>>>,
+}
+
+impl MockTransport {
+    pub(crate) fn new(status: u16) -> Self {
+        Self {
+            status,
+            requests: Default::default(),
+        }
+    }
+}
+
+impl Transport for MockTransport {
+    fn send(&self, request: &TransportRequest) -> TelemetryResult<TransportResponse> {
+        let body = serde_json::from_slice(&request.body).unwrap();
+        self.requests.lock().unwrap().push(body);
+        Ok(TransportResponse {
+            status: self.status,
+            retry_after: None,
+        })
+    }
+}
+
+pub(crate) fn setup() -> (TempDir, String) {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("telemetry.json");
+    (temp_dir, config_path.to_str().unwrap().to_string())
+}
+
+pub(crate) fn enable_telemetry(config_path: &str) -> TelemetryConfig {
+    let mut config = TelemetryConfig::new("zksync-telemetry", Some(config_path.into())).unwrap();
+    config.update_consent(true).unwrap();
+    config
+}
+
+pub(crate) fn posthog_telemetry(config_path: &str, transport: MockTransport) -> Telemetry {
+    Telemetry::builder("test-app", "1.0.0", "zksync-telemetry")
+        .keys(keys(Some("phc_fake_key"), None))
+        .custom_config_path(Some(config_path.into()))
+        .transport(transport)
+        .retry_policy(RetryPolicy::none())
+        // The hook is global, panics in other tests would be reported here
+        .panic_reporting(PanicReporting::Disabled)
+        .build()
+        .unwrap()
+}
+
+/// Instance with consent given, sending PostHog events to the returned transport.
+/// The config is removed when the directory is dropped.
+pub(crate) fn enabled_telemetry() -> (TempDir, Telemetry, MockTransport) {
+    let (temp_dir, config_path) = setup();
+    enable_telemetry(&config_path);
+    let transport = MockTransport::new(200);
+    let telemetry = posthog_telemetry(&config_path, transport.clone());
+    (temp_dir, telemetry, transport)
+}
+
+/// Waits until the queued events are sent and returns the bodies of all requests
+pub(crate) fn sent_requests(
+    telemetry: &Telemetry,
+    transport: &MockTransport,
+) -> Vec<serde_json::Value> {
+    assert!(telemetry.flush(Duration::from_secs(5)));
+    transport.requests.lock().unwrap().clone()
+}
+
+pub(crate) fn keys(posthog_key: Option<&str>, sentry_dsn: Option<&str>) -> TelemetryKeys {
+    TelemetryKeys::with_keys(
+        posthog_key.map(str::to_string),
+        sentry_dsn.map(str::to_string),
+    )
+    .unwrap()
+}
@@ -0,0 +1,285 @@
+//! [`tracing`] integration, enabled with the `tracing` feature.
+use crate::{get_telemetry, ErrorReport, Telemetry, TelemetryProps};
+use serde_json::{Map, Value};
+use std::fmt;
+use std::time::Instant;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Forwards `tracing` events and spans to [`Telemetry`].
+///
+/// - Events and spans with the configured target (`telemetry` by default), or
+///   with the marker field (`telemetry = true` by default) are tracked as PostHog
+///   events. Events are named after their message and spans after their name,
+///   and their fields become event properties. Spans get a `duration_ms` property
+///   and are tracked when closed.
+/// - `ERROR` events of any target are tracked as errors, with their fields as properties.
+///
+/// Everything is tracked with the detached methods, so the layer never blocks,
+/// and consent is respected as with direct calls.
+///
+/// ```no_run
+/// use tracing_subscriber::prelude::*;
+/// use zksync_telemetry::TelemetryLayer;
+///
+/// tracing_subscriber::registry()
+///     .with(TelemetryLayer::new())
+///     .init();
+///
+/// tracing::info!(target: "telemetry", network = "sepolia", "contract_deployed");
+/// tracing::info!(telemetry = true, "command_executed");
+/// ```
+pub struct TelemetryLayer {
+    telemetry: Option<&'static Telemetry>,
+    target: String,
+    marker_field: String,
+    track_errors: bool,
+}
+
+impl TelemetryLayer {
+    /// Creates a layer forwarding to the global instance set by
+    /// [`init_telemetry`](crate::init_telemetry). Until it's set, nothing is forwarded.
+    pub fn new() -> Self {
+        Self {
+            telemetry: None,
+            target: "telemetry".to_string(),
+            marker_field: "telemetry".to_string(),
+            track_errors: true,
+        }
+    }
+
+    /// Creates a layer forwarding to the given instance instead of the global one
+    pub fn with_telemetry(telemetry: &'static Telemetry) -> Self {
+        Self {
+            telemetry: Some(telemetry),
+            ..Self::new()
+        }
+    }
+
+    /// Sets the target of events and spans to track
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.target = target.into();
+        self
+    }
+
+    /// Sets the name of the field marking events and spans to track
+    pub fn marker_field(mut self, marker_field: impl Into<String>) -> Self {
+        self.marker_field = marker_field.into();
+        self
+    }
+
+    /// Whether `ERROR` events are tracked as errors, enabled by default
+    pub fn track_errors(mut self, track_errors: bool) -> Self {
+        self.track_errors = track_errors;
+        self
+    }
+
+    fn telemetry(&self) -> Option<&'static Telemetry> {
+        self.telemetry.or_else(get_telemetry)
+    }
+
+    /// Returns the properties to track, or `None` if the event or span isn't selected
+    fn selected(&self, target: &str, mut fields: FieldVisitor) -> Option<FieldVisitor> {
+        let marked = fields.fields.remove(&self.marker_field) == Some(Value::Bool(true));
+        (marked || target == self.target).then_some(fields)
+    }
+}
+
+impl Default for TelemetryLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// State of a selected span, stored in its extensions
+struct TrackedSpan {
+    started_at: Instant,
+    fields: Map<String, Value>,
+}
+
+impl<S> Layer<S> for TelemetryLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut fields = FieldVisitor::default();
+        attrs.record(&mut fields);
+        let Some(fields) = self.selected(attrs.metadata().target(), fields) else {
+            return;
+        };
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(TrackedSpan {
+                started_at: Instant::now(),
+                fields: fields.fields,
+            });
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(tracked) = extensions.get_mut::<TrackedSpan>() {
+            let mut fields = FieldVisitor::default();
+            values.record(&mut fields);
+            tracked.fields.extend(fields.fields);
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let Some(telemetry) = self.telemetry() else {
+            return;
+        };
+        let metadata = event.metadata();
+        let mut fields = FieldVisitor::default();
+        event.record(&mut fields);
+
+        if self.track_errors && *metadata.level() == Level::ERROR {
+            let error = TracingError {
+                message: fields.message.clone().unwrap_or_default(),
+            };
+            let properties = TelemetryProps::from_map(fields.fields.clone());
+            telemetry.track_error_detached(ErrorReport::new(&error).with_properties(properties));
+        }
+
+        if let Some(mut fields) = self.selected(metadata.target(), fields) {
+            let name = fields
+                .message
+                .take()
+                .unwrap_or_else(|| metadata.name().to_string());
+            telemetry.track_event_detached(&name, TelemetryProps::from_map(fields.fields));
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(tracked) = span.extensions_mut().remove::<TrackedSpan>() else {
+            return;
+        };
+        let Some(telemetry) = self.telemetry() else {
+            return;
+        };
+        let mut fields = tracked.fields;
+        fields.insert(
+            "duration_ms".to_string(),
+            (tracked.started_at.elapsed().as_millis() as u64).into(),
+        );
+        telemetry.track_event_detached(span.name(), TelemetryProps::from_map(fields));
+    }
+}
+
+/// Collects the fields of events and spans, keeping the message apart
+#[derive(Default)]
+struct FieldVisitor {
+    message: Option<String>,
+    fields: Map<String, Value>,
+}
+
+impl FieldVisitor {
+    fn insert(&mut self, field: &Field, value: Value) {
+        if field.name() == "message" {
+            self.message = value.as_str().map(str::to_string);
+        } else {
+            self.fields.insert(field.name().to_string(), value);
+        }
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, format!("{:?}", value).into());
+    }
+}
+
+/// Error reported for `ERROR` events
+#[derive(Debug)]
+struct TracingError {
+    message: String,
+}
+
+impl fmt::Display for TracingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for TracingError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{enabled_telemetry, sent_requests};
+    use tracing_subscriber::prelude::*;
+
+    #[test]
+    fn test_tracing_layer() {
+        let (_temp_dir, telemetry, transport) = enabled_telemetry();
+        let telemetry: &'static Telemetry = Box::leak(Box::new(telemetry));
+        let subscriber =
+            tracing_subscriber::registry().with(TelemetryLayer::with_telemetry(telemetry));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(target: "telemetry", network = "sepolia", "contract_deployed");
+            tracing::info!(telemetry = true, count = 3, "command_executed");
+            tracing::info!("not tracked");
+            tracing::error!(stage = "verification", "something failed");
+            let span =
+                tracing::info_span!("deploy", telemetry = true, contract = tracing::field::Empty);
+            span.record("contract", "Greeter");
+            drop(span);
+            let _untracked = tracing::info_span!("untracked");
+        });
+
+        let requests = sent_requests(telemetry, &transport);
+        let events: Vec<_> = requests
+            .iter()
+            .map(|request| request["event"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            events,
+            [
+                "contract_deployed",
+                "command_executed",
+                "$exception",
+                "deploy"
+            ]
+        );
+        assert_eq!(requests[0]["properties"]["network"], "sepolia");
+        assert_eq!(requests[1]["properties"]["count"], 3);
+        assert!(requests[1]["properties"].get("telemetry").is_none());
+        assert_eq!(
+            requests[2]["properties"]["$exception_list"][0]["value"],
+            "something failed"
+        );
+        assert_eq!(requests[2]["properties"]["stage"], "verification");
+        assert_eq!(requests[3]["properties"]["contract"], "Greeter");
+        assert!(requests[3]["properties"]["duration_ms"].is_u64());
+    }
+}
@@ -0,0 +1,80 @@
+//! HTTP transport used to deliver telemetry to PostHog.
+use crate::error::{TelemetryError, TelemetryResult};
+use crate::retry::parse_retry_after;
+use std::time::Duration;
+
+/// JSON `POST` request to be sent by a [`Transport`]
+#[derive(Clone, Debug)]
+pub struct TransportRequest {
+    pub url: String,
+    pub body: Vec<u8>,
+}
+
+/// Response received by a [`Transport`]
+#[derive(Clone, Debug)]
+pub struct TransportResponse {
+    pub status: u16,
+    /// Delay requested by the server via the `Retry-After` header
+    pub retry_after: Option<Duration>,
+}
+
+/// Sends HTTP requests on behalf of the telemetry client.
+///
+/// Requests are sent from the telemetry background thread, so implementations
+/// may block and don't depend on any async runtime.
+pub trait Transport: Send + Sync {
+    /// Sends the request. Non-2xx statuses are returned as responses, not errors.
+    fn send(&self, request: &TransportRequest) -> TelemetryResult<TransportResponse>;
+}
+
+/// Default [`Transport`] based on a blocking HTTP client
+pub struct HttpTransport {
+    agent: ureq::Agent,
+}
+
+impl HttpTransport {
+    pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+    pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+    pub fn new() -> Self {
+        Self::with_timeouts(Self::DEFAULT_CONNECT_TIMEOUT, Self::DEFAULT_REQUEST_TIMEOUT)
+    }
+
+    /// Creates a transport with the given timeouts for establishing a
+    /// connection and for the whole request
+    pub fn with_timeouts(connect_timeout: Duration, request_timeout: Duration) -> Self {
+        Self {
+            agent: ureq::AgentBuilder::new()
+                .timeout_connect(connect_timeout)
+                .timeout(request_timeout)
+                .build(),
+        }
+    }
+}
+
+impl Default for HttpTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transport for HttpTransport {
+    fn send(&self, request: &TransportRequest) -> TelemetryResult<TransportResponse> {
+        match self
+            .agent
+            .post(&request.url)
+            .set("Content-Type", "application/json")
+            .send_bytes(&request.body)
+        {
+            Ok(response) => Ok(TransportResponse {
+                status: response.status(),
+                retry_after: None,
+            }),
+            Err(ureq::Error::Status(status, response)) => Ok(TransportResponse {
+                status,
+                retry_after: response.header("Retry-After").and_then(parse_retry_after),
+            }),
+            Err(e) => Err(TelemetryError::SendError(e.to_string())),
+        }
+    }
+}
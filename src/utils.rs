@@ -26,3 +26,11 @@ pub(crate) fn prompt_yes_no(prompt: &str) -> bool {
         false
     }
 }
+
+/// Prints a diagnostic message to stderr if `ZKSYNC_TELEMETRY_DEBUG` is set.
+/// Telemetry problems are otherwise kept silent so they never bother the user.
+pub(crate) fn debug_log(message: &str) {
+    if std::env::var_os("ZKSYNC_TELEMETRY_DEBUG").is_some() {
+        eprintln!("[zksync_telemetry] {}", message);
+    }
+}
@@ -0,0 +1,270 @@
+//! Background thread that delivers events to PostHog.
+//!
+//! Both the async and the blocking API hand their events to this thread, so they
+//! behave identically and work on any executor, or without one.
+use crate::diagnostics::{DiagnosticsCounters, TelemetryDiagnostics};
+use crate::error::{TelemetryError, TelemetryResult};
+use crate::posthog::PostHogEvent;
+use crate::retry::{send_with_retry, RetryPolicy};
+use crate::secret::SecretString;
+use crate::transport::{Transport, TransportRequest};
+use futures_channel::oneshot;
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+/// Number of events waiting to be sent, beyond which new events are dropped
+const QUEUE_CAPACITY: usize = 1000;
+/// How often `flush` checks for room in a full queue
+const FLUSH_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+type Callback = Box<dyn FnOnce(TelemetryResult<()>) + Send>;
+
+enum Job {
+    Capture {
+        event: PostHogEvent,
+        done: Callback,
+    },
+    /// Replies once every job queued before it has been processed
+    Flush(mpsc::SyncSender<()>),
+}
+
+#[derive(Clone)]
+pub(crate) struct PostHogWorker {
+    sender: mpsc::SyncSender<Job>,
+    counters: Arc<DiagnosticsCounters>,
+}
+
+impl PostHogWorker {
+    pub(crate) fn spawn(
+        api_key: SecretString,
+        host: &str,
+        transport: Arc<dyn Transport>,
+        retry_policy: RetryPolicy,
+    ) -> TelemetryResult<Self> {
+        Self::spawn_with_capacity(api_key, host, transport, retry_policy, QUEUE_CAPACITY)
+    }
+
+    fn spawn_with_capacity(
+        api_key: SecretString,
+        host: &str,
+        transport: Arc<dyn Transport>,
+        retry_policy: RetryPolicy,
+        capacity: usize,
+    ) -> TelemetryResult<Self> {
+        let url = format!("{}/capture/", host.trim_end_matches('/'));
+        let (sender, receiver) = mpsc::sync_channel::<Job>(capacity);
+        let counters = Arc::new(DiagnosticsCounters::default());
+        let worker_counters = counters.clone();
+
+        std::thread::Builder::new()
+            .name("zksync-telemetry".to_string())
+            .spawn(move || {
+                // Runs until every sender is dropped and the queue is drained
+                for job in receiver {
+                    match job {
+                        Job::Capture { event, done } => {
+                            let result = send_event(
+                                transport.as_ref(),
+                                &retry_policy,
+                                &url,
+                                &api_key,
+                                &event,
+                            );
+                            match &result {
+                                Ok(()) => worker_counters.record_sent(),
+                                Err(e) => worker_counters.record_failure(&e.to_string()),
+                            }
+                            done(result);
+                        }
+                        Job::Flush(reply) => {
+                            let _ = reply.send(());
+                        }
+                    }
+                }
+            })
+            .map_err(|e| {
+                TelemetryError::InitializationError(format!(
+                    "Failed to spawn telemetry thread: {}",
+                    e
+                ))
+            })?;
+
+        Ok(Self { sender, counters })
+    }
+
+    pub(crate) async fn capture(&self, event: PostHogEvent) -> TelemetryResult<()> {
+        let (reply, receiver) = oneshot::channel();
+        self.enqueue(event, move |result| {
+            let _ = reply.send(result);
+        })?;
+        receiver.await.map_err(|_| worker_stopped())?
+    }
+
+    /// Blocks the current thread until the event is sent
+    pub(crate) fn capture_blocking(&self, event: PostHogEvent) -> TelemetryResult<()> {
+        let (reply, receiver) = mpsc::sync_channel(1);
+        self.enqueue(event, move |result| {
+            let _ = reply.send(result);
+        })?;
+        receiver.recv().map_err(|_| worker_stopped())?
+    }
+
+    /// Blocks the current thread until the event is sent or `timeout` elapses
+    pub(crate) fn capture_blocking_timeout(
+        &self,
+        event: PostHogEvent,
+        timeout: Duration,
+    ) -> TelemetryResult<()> {
+        let (reply, receiver) = mpsc::sync_channel(1);
+        self.enqueue(event, move |result| {
+            let _ = reply.send(result);
+        })?;
+        receiver.recv_timeout(timeout).map_err(|e| match e {
+            mpsc::RecvTimeoutError::Timeout => {
+                TelemetryError::SendError("Timed out sending telemetry".to_string())
+            }
+            mpsc::RecvTimeoutError::Disconnected => worker_stopped(),
+        })?
+    }
+
+    /// Queues the event without waiting for it to be sent, or drops it if the queue is full.
+    /// Failures are only recorded in the diagnostics counters.
+    pub(crate) fn capture_detached(&self, event: PostHogEvent) {
+        let _ = self.enqueue(event, |_| {});
+    }
+
+    /// Waits until every event queued so far has been processed.
+    /// Returns `false` if `timeout` elapsed first.
+    pub(crate) fn flush(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now().checked_add(timeout);
+        let (reply, receiver) = mpsc::sync_channel(1);
+        let mut job = Job::Flush(reply);
+        // Waits for room in a full queue, but no longer than the timeout
+        loop {
+            match self.sender.try_send(job) {
+                Ok(()) => break,
+                Err(mpsc::TrySendError::Full(rejected))
+                    if deadline.is_none_or(|deadline| Instant::now() < deadline) =>
+                {
+                    job = rejected;
+                    std::thread::sleep(FLUSH_POLL_INTERVAL);
+                }
+                Err(_) => return false,
+            }
+        }
+        let remaining = deadline.map_or(timeout, |deadline| {
+            deadline.saturating_duration_since(Instant::now())
+        });
+        receiver.recv_timeout(remaining).is_ok()
+    }
+
+    pub(crate) fn diagnostics(&self) -> TelemetryDiagnostics {
+        self.counters.snapshot()
+    }
+
+    fn enqueue(
+        &self,
+        event: PostHogEvent,
+        done: impl FnOnce(TelemetryResult<()>) + Send + 'static,
+    ) -> TelemetryResult<()> {
+        let job = Job::Capture {
+            event,
+            done: Box::new(done),
+        };
+        match self.sender.try_send(job) {
+            Ok(()) => {
+                self.counters.record_enqueued();
+                Ok(())
+            }
+            Err(mpsc::TrySendError::Full(_)) => {
+                self.counters.record_dropped();
+                Err(TelemetryError::SendError(
+                    "Telemetry queue is full, event dropped".to_string(),
+                ))
+            }
+            Err(mpsc::TrySendError::Disconnected(_)) => {
+                let error = worker_stopped();
+                self.counters.record_failure(&error.to_string());
+                Err(error)
+            }
+        }
+    }
+}
+
+fn send_event(
+    transport: &dyn Transport,
+    retry_policy: &RetryPolicy,
+    url: &str,
+    api_key: &SecretString,
+    event: &PostHogEvent,
+) -> TelemetryResult<()> {
+    let request = TransportRequest {
+        url: url.to_string(),
+        body: event.to_payload(api_key.expose_secret())?,
+    };
+    let response = send_with_retry(transport, &request, retry_policy)?;
+    if !(200..300).contains(&response.status) {
+        return Err(TelemetryError::PostHogError(format!(
+            "PostHog responded with status {}",
+            response.status
+        )));
+    }
+    Ok(())
+}
+
+fn worker_stopped() -> TelemetryError {
+    TelemetryError::SendError("Telemetry thread has stopped".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::TransportResponse;
+    use std::sync::Mutex;
+
+    /// Blocks every request until the sender of `release` is dropped
+    struct BlockingTransport {
+        release: Mutex<mpsc::Receiver<()>>,
+    }
+
+    impl Transport for BlockingTransport {
+        fn send(&self, _request: &TransportRequest) -> TelemetryResult<TransportResponse> {
+            let _ = self.release.lock().unwrap().recv();
+            Ok(TransportResponse {
+                status: 200,
+                retry_after: None,
+            })
+        }
+    }
+
+    #[test]
+    fn test_full_queue_drops_events() {
+        let (release, receiver) = mpsc::channel();
+        let transport = BlockingTransport {
+            release: Mutex::new(receiver),
+        };
+        let worker = PostHogWorker::spawn_with_capacity(
+            SecretString::new("phc_fake_key"),
+            "https://posthog.test",
+            Arc::new(transport),
+            RetryPolicy::none(),
+            2,
+        )
+        .unwrap();
+
+        // At most one event is being sent and two are queued, the rest is dropped
+        for _ in 0..10 {
+            worker.capture_detached(PostHogEvent::new("test_event", "instance"));
+        }
+        let diagnostics = worker.diagnostics();
+        assert!(diagnostics.events_dropped >= 7);
+        assert_eq!(diagnostics.events_enqueued + diagnostics.events_dropped, 10);
+
+        drop(release);
+        assert!(worker.flush(Duration::from_secs(5)));
+        assert_eq!(
+            worker.diagnostics().events_sent,
+            diagnostics.events_enqueued
+        );
+    }
+}